@@ -19,7 +19,19 @@ def interpolate_and_plot(csv_file):
     """
     # Attempt to load the file
     try:
-        df = pd.read_csv(csv_file, header=None, names=["time", "power", "azimuth", "elevation"])
+        # newer recordings start with a header row (after the # metadata
+        # comments), older ones don't
+        with open(csv_file) as f:
+            first_line = next((l for l in f if not l.startswith("#")), "")
+            has_header = first_line.startswith("elapsed_s")
+        df = pd.read_csv(
+            csv_file,
+            comment="#",
+            header=0 if has_header else None,
+            names=["time", "power", "azimuth", "elevation"],
+            # newer recordings also have the plan target name
+            usecols=range(4),
+        )
     except Exception as e:
         print(f"Failed to read or parse {csv_file}: {e}")
         return
@@ -0,0 +1,201 @@
+use std::{io, time::Duration};
+
+use log::{error, info};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf},
+    sync::broadcast,
+    task::JoinHandle,
+    time::{timeout, timeout_at, Instant},
+};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+use crate::{
+    dish_actions::MotionOptions,
+    dish_controller::{azimuth_distance, DishState, SerialSettings, POLL_INTERVAL},
+    dish_driver::{DishCommand, DishResponse},
+    error::DishError,
+};
+
+/// responses a slow `responses` stream can fall behind by before it misses
+/// some
+const RESPONSES_KEPT: usize = 256;
+/// how much longer than the averaging `read_rf` waits for the reading
+const RF_REPLY_MARGIN: Duration = Duration::from_secs(3);
+
+/// Controls the dish over serial from async code, with the `async` feature.
+///
+/// Unlike `DishSerialController` nothing polls in the background and there
+/// is no bus: commands are awaited as they're written, the replies come from
+/// `responses`, and `move_to` resolves once the dish got there:
+///
+/// ```no_run
+/// # async fn run() -> Result<(), tailgaters::error::DishError> {
+/// use tailgaters::{async_controller::AsyncDishSerialController, dish_controller::SerialSettings};
+///
+/// let mut dish = AsyncDishSerialController::open("/dev/ttyACM0", SerialSettings::default())?;
+/// dish.move_to(160.2, 31.5).await?;
+/// println!("{}", dish.read_rf(1).await?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncDishSerialController {
+    writer: WriteHalf<SerialStream>,
+    pub serial_port_name: String,
+    responses: broadcast::Sender<DishResponse>,
+    reader: JoinHandle<()>,
+    /// calibration, pointing offset and soft limits the commands use, and
+    /// the position from the last replies `move_to` waited for
+    pub state: DishState,
+    /// only `tolerance_deg` and `timeout_ms` apply, the moves are always
+    /// `azangle` and `elangle`
+    pub motion: MotionOptions,
+}
+
+impl AsyncDishSerialController {
+    /// Opens the port and starts reading it, which needs to happen inside a
+    /// tokio runtime.
+    pub fn open(port_name: &str, settings: SerialSettings) -> Result<Self, DishError> {
+        let port = tokio_serial::new(port_name, settings.baudrate)
+            .data_bits(settings.data_bits)
+            .flow_control(settings.flow_control)
+            .stop_bits(settings.stop_bits)
+            .parity(settings.parity)
+            .open_native_async()
+            .map_err(|source| DishError::PortOpen {
+                port: port_name.to_string(),
+                source,
+            })?;
+        info!("Serial port '{}' opened at {}.", port_name, settings);
+
+        let (reader, writer) = tokio::io::split(port);
+        let (responses, _) = broadcast::channel(RESPONSES_KEPT);
+        let reader = tokio::spawn(read_responses(reader, responses.clone()));
+
+        Ok(AsyncDishSerialController {
+            writer,
+            serial_port_name: port_name.to_string(),
+            responses,
+            reader,
+            state: DishState::default(),
+            motion: MotionOptions::default(),
+        })
+    }
+
+    /// Writes the command, clamped to the soft limits like the app does.
+    pub async fn send_command(&mut self, command: DishCommand) -> Result<(), DishError> {
+        let command = self
+            .state
+            .soft_limits
+            .clamp(command, &self.state.elevation_calibration);
+        let line = format!("{}\r", command.serialize());
+        self.writer.write_all(line.as_bytes()).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Every reply the dish sends from now on. A stream that falls more than
+    /// `RESPONSES_KEPT` behind skips the replies it missed.
+    pub fn responses(&self) -> impl Stream<Item = DishResponse> {
+        BroadcastStream::new(self.responses.subscribe()).filter_map(Result::ok)
+    }
+
+    /// Moves to the position and resolves once the readback is within
+    /// `motion.tolerance_deg` on both axes, polling it meanwhile. Gives up
+    /// with `DishError::Timeout` after `motion.timeout_ms`.
+    pub async fn move_to(&mut self, azimuth: f64, elevation: f64) -> Result<(), DishError> {
+        let mut responses = self.responses.subscribe();
+        let move_timeout = Duration::from_millis(self.motion.timeout_ms);
+        let command = DishCommand::SetAzimuthAngle(self.state.firmware_azimuth(azimuth));
+        self.send_command(command.clone()).await?;
+        self.send_command(DishCommand::SetElevationAngle(
+            self.state.dish_elevation(elevation),
+        ))
+        .await?;
+
+        let arrived = async {
+            loop {
+                self.send_command(DishCommand::GetAzimuth).await?;
+                self.send_command(DishCommand::GetElevation).await?;
+
+                let next_poll = Instant::now() + POLL_INTERVAL;
+                while let Ok(response) = timeout_at(next_poll, responses.recv()).await {
+                    // lagging behind only loses old positions
+                    if let Ok(response) = response {
+                        self.state.update_from_response(&response);
+                    }
+                }
+                if azimuth_distance(self.state.azimuth_angle, azimuth) <= self.motion.tolerance_deg
+                    && (self.state.elevation_angle - elevation).abs() <= self.motion.tolerance_deg
+                {
+                    info!(
+                        "Set position to azimuth: {}, elevation: {}",
+                        azimuth, elevation
+                    );
+                    return Ok::<(), DishError>(());
+                }
+            }
+        };
+        timeout(move_timeout, arrived)
+            .await
+            .map_err(|_| DishError::Timeout(command))?
+    }
+
+    /// Takes an rf reading averaged over `seconds` where the dish is and
+    /// waits for it.
+    pub async fn read_rf(&mut self, seconds: u32) -> Result<f64, DishError> {
+        let mut responses = self.responses();
+        let command = DishCommand::RfWatch(seconds);
+        self.send_command(command.clone()).await?;
+
+        let reading = async {
+            while let Some(response) = responses.next().await {
+                if let DishResponse::RfPower(power) = response {
+                    self.state.update_from_response(&response);
+                    return Some(power);
+                }
+            }
+            None
+        };
+        let reply_timeout = Duration::from_secs(seconds as u64) + RF_REPLY_MARGIN;
+        match timeout(reply_timeout, reading).await {
+            Ok(Some(power)) => Ok(power),
+            Ok(None) => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Err(_) => Err(DishError::Timeout(command)),
+        }
+    }
+}
+
+impl Drop for AsyncDishSerialController {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Parses the lines of the dish into `responses` until the port closes.
+async fn read_responses(
+    reader: ReadHalf<SerialStream>,
+    responses: broadcast::Sender<DishResponse>,
+) {
+    let mut lines = BufReader::new(reader).split(b'\n');
+    loop {
+        let line = match lines.next_segment().await {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                info!("End of the dish output");
+                return;
+            }
+            Err(e) => {
+                error!("Reading from the dish failed: {}", e);
+                return;
+            }
+        };
+
+        // bytes that aren't utf-8 can't be part of a reply anyway
+        let response = DishResponse::parse(String::from_utf8_lossy(&line).trim());
+        // without subscribers nobody is waiting for it
+        if let Some(response) = response {
+            let _ = responses.send(response);
+        }
+    }
+}
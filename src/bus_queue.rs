@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+
+use crate::{dish_driver::DishCommand, GlobalBus};
+
+/// Bus messages received but not handled yet.
+///
+/// Position polls go behind everything else, so a nudge or an action's move
+/// isn't stuck behind a burst of them. A poll that's already waiting isn't
+/// queued twice, which keeps the queue bounded when polls back up.
+#[derive(Default)]
+pub struct BusQueue {
+    urgent: VecDeque<GlobalBus>,
+    polls: VecDeque<(usize, DishCommand)>,
+}
+
+impl BusQueue {
+    pub fn is_empty(&self) -> bool {
+        self.urgent.is_empty() && self.polls.is_empty()
+    }
+
+    pub fn push(&mut self, event: GlobalBus) {
+        match event {
+            GlobalBus::PollCommand(dish, command) => {
+                let poll = (dish, command);
+                if !self.polls.contains(&poll) {
+                    self.polls.push_back(poll);
+                }
+            }
+            event => self.urgent.push_back(event),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<GlobalBus> {
+        self.urgent.pop_front().or_else(|| {
+            self.polls
+                .pop_front()
+                .map(|(dish, command)| GlobalBus::PollCommand(dish, command))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_polls_go_last() {
+        let mut queue = BusQueue::default();
+        queue.push(GlobalBus::PollCommand(0, DishCommand::GetAzimuth));
+        queue.push(GlobalBus::PollCommand(0, DishCommand::GetElevation));
+        queue.push(GlobalBus::PollCommand(0, DishCommand::GetAzimuth));
+        queue.push(GlobalBus::DishCommand(DishCommand::NudgeAzimuthCw));
+        queue.push(GlobalBus::DishCommand(DishCommand::NudgeAzimuthCcw));
+
+        let mut order = vec![];
+        while let Some(event) = queue.pop() {
+            order.push(match event {
+                GlobalBus::DishCommand(command) => (None, command),
+                GlobalBus::PollCommand(dish, command) => (Some(dish), command),
+                event => panic!("unexpected {:?}", event),
+            });
+        }
+        assert_eq!(
+            order,
+            vec![
+                (None, DishCommand::NudgeAzimuthCw),
+                (None, DishCommand::NudgeAzimuthCcw),
+                (Some(0), DishCommand::GetAzimuth),
+                (Some(0), DishCommand::GetElevation),
+            ]
+        );
+        assert!(queue.is_empty());
+    }
+}
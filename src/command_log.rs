@@ -0,0 +1,55 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+
+use crate::dish_driver::DishCommand;
+
+/// Audit trail of the commands sent to the dishes, one
+/// `[unix time] dish <n>: <command>` line each, to reconstruct what a session
+/// did after the fact.
+pub struct CommandLog {
+    /// None once writing to it failed, the session goes on regardless
+    file: Option<File>,
+    /// also log the `azacc`/`elacc` position polls, several a second
+    polls: bool,
+}
+
+impl CommandLog {
+    pub fn open(path: &Path, polls: bool) -> io::Result<CommandLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(CommandLog {
+            file: Some(file),
+            polls,
+        })
+    }
+
+    /// `dish` is the index of the dish, 0 for the first `--port`
+    pub fn record(&mut self, dish: usize, command: &DishCommand) {
+        let is_poll = matches!(command, DishCommand::GetAzimuth | DishCommand::GetElevation);
+        if is_poll && !self.polls {
+            return;
+        }
+        let Some(file) = &mut self.file else {
+            return;
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        if let Err(e) = writeln!(
+            file,
+            "[{:.3}] dish {}: {}",
+            now.as_secs_f64(),
+            dish + 1,
+            command
+        ) {
+            error!("Stopped writing the command log: {}", e);
+            self.file = None;
+        }
+    }
+}
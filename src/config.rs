@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+
+use clap::{parser::ValueSource, ArgMatches};
+use color_eyre::{eyre::WrapErr, Result};
+use serde::Deserialize;
+
+use crate::{Cli, ElevationUnit};
+
+/// Per-site defaults loaded with `--config`, every field is optional and
+/// flags given on the command line take precedence over the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub port: Option<String>,
+    pub baudrate: Option<u32>,
+    pub az_start: Option<i32>,
+    pub az_end: Option<i32>,
+    pub el_start: Option<i32>,
+    pub el_end: Option<i32>,
+    pub el_unit: Option<ElevationUnit>,
+    pub step: Option<f64>,
+    pub dwell_ms: Option<u64>,
+    pub samples_per_point: Option<u32>,
+    pub output: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub rotate_minutes: Option<u64>,
+    pub rotate_mb: Option<u64>,
+    pub log_file: Option<PathBuf>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub el_count_min: Option<i32>,
+    pub el_count_max: Option<i32>,
+    pub el_angle_max: Option<f64>,
+    pub az_count_offset: Option<i32>,
+    pub az_counts_per_degree: Option<f64>,
+    pub az_offset: Option<f64>,
+    pub el_offset: Option<f64>,
+    pub stow_az: Option<f64>,
+    pub rf_watch: Option<u32>,
+    pub elevation_maintain: Option<bool>,
+    pub azimuth_heading: Option<bool>,
+    pub smoothing: Option<f64>,
+    pub saturation_limit: Option<f64>,
+    pub saturation_cooldown_ms: Option<u64>,
+    pub position_tolerance: Option<f64>,
+    pub position_timeout_ms: Option<u64>,
+    pub position_retries: Option<u32>,
+    pub rehome: Option<bool>,
+    pub az_min: Option<f64>,
+    pub az_max: Option<f64>,
+    pub el_min: Option<f64>,
+    pub el_max: Option<f64>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("could not read config file {}", path.display()))?;
+        toml::from_str(&text)
+            .wrap_err_with(|| format!("could not parse config file {}", path.display()))
+    }
+
+    /// Fills in the values of `args` that weren't given explicitly on the command line.
+    pub fn apply(self, args: &mut Cli, matches: &ArgMatches) {
+        let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        // fields whose Cli counterpart has a default value
+        macro_rules! merge {
+            ($($field:ident),*) => {
+                $(
+                    if let Some(value) = self.$field {
+                        if !from_cli(stringify!($field)) {
+                            args.$field = value;
+                        }
+                    }
+                )*
+            };
+        }
+
+        // fields that are optional on the Cli too
+        macro_rules! merge_optional {
+            ($($field:ident),*) => {
+                $(
+                    if self.$field.is_some() && !from_cli(stringify!($field)) {
+                        args.$field = self.$field;
+                    }
+                )*
+            };
+        }
+
+        if let Some(port) = self.port {
+            if !from_cli("port") {
+                args.port = vec![port];
+            }
+        }
+        merge!(baudrate, az_start, az_end, el_start, el_end, el_unit, step, dwell_ms, output_dir);
+        merge!(el_count_min, el_count_max, el_angle_max, stow_az, rf_watch);
+        merge!(
+            elevation_maintain,
+            azimuth_heading,
+            smoothing,
+            saturation_limit,
+            saturation_cooldown_ms
+        );
+        merge!(
+            position_tolerance,
+            position_timeout_ms,
+            position_retries,
+            rehome
+        );
+        merge!(samples_per_point, az_offset, el_offset);
+        merge!(az_min, az_max, el_min);
+        merge_optional!(output, latitude, longitude, el_max);
+        merge_optional!(az_count_offset, az_counts_per_degree);
+        merge_optional!(rotate_minutes, rotate_mb, log_file);
+    }
+}
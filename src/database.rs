@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::{Cli, RfPowerSample};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at REAL NOT NULL,
+    port TEXT NOT NULL,
+    scan INTEGER NOT NULL,
+    az_start REAL NOT NULL,
+    az_end REAL NOT NULL,
+    el_start REAL NOT NULL,
+    el_end REAL NOT NULL,
+    step REAL NOT NULL,
+    dwell_ms INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS samples (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    timestamp REAL NOT NULL,
+    power REAL NOT NULL,
+    azimuth REAL NOT NULL,
+    elevation REAL NOT NULL
+);
+";
+
+/// Records every rf sample to an SQLite database, grouped by session so
+/// acquisitions can be compared with plain SQL.
+pub struct SampleDatabase {
+    connection: Connection,
+    session_id: i64,
+}
+
+impl SampleDatabase {
+    /// Opens (creating the schema if needed) the database and starts a new
+    /// session with the current scan parameters.
+    pub fn open(path: &Path, args: &Cli) -> rusqlite::Result<SampleDatabase> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+
+        connection.execute(
+            "INSERT INTO sessions (started_at, port, scan, az_start, az_end, el_start, el_end, step, dwell_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                unix_time(std::time::SystemTime::now()),
+                args.port.join(","),
+                args.scan,
+                args.az_start,
+                args.az_end,
+                args.el_start,
+                args.el_end,
+                args.step,
+                args.dwell_ms as i64,
+            ],
+        )?;
+        let session_id = connection.last_insert_rowid();
+
+        Ok(SampleDatabase {
+            connection,
+            session_id,
+        })
+    }
+
+    pub fn session_id(&self) -> i64 {
+        self.session_id
+    }
+
+    pub fn insert(&self, sample: &RfPowerSample) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "INSERT INTO samples (session_id, timestamp, power, azimuth, elevation)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                self.session_id,
+                unix_time(sample.timestamp),
+                sample.power,
+                sample.azimuth,
+                sample.elevation,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+fn unix_time(time: std::time::SystemTime) -> f64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
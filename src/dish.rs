@@ -0,0 +1,236 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{debug, error};
+
+use crate::{
+    dish_actions::{ActionManager, DishPosition, MotionOptions},
+    dish_controller::{
+        DishController, DishSerialController, DishState, ElevationCalibration, SerialSettings,
+    },
+    dish_driver::{DishCommand, DishResponse},
+    error::DishError,
+    simulator::DishSimulator,
+    GlobalBus, RfPowerSample,
+};
+
+/// seconds of readings the firmware averages for `read_rf`
+const RF_WATCH_S: u32 = 1;
+/// how much longer than the averaging `read_rf` waits for the reading
+const RF_REPLY_MARGIN: Duration = Duration::from_secs(3);
+/// samples kept for `samples` until they're read, newer ones are dropped
+const SAMPLES_KEPT: usize = 1024;
+/// how often the bus thread checks whether the `Dish` was dropped
+const STOP_CHECK: Duration = Duration::from_millis(100);
+
+/// A dish for use from another program, without the TUI.
+///
+/// A thread passes the position polls to the controller and keeps the state
+/// up to date from the replies, the way the app does, so the calls here can
+/// simply block until the dish got there or answered:
+///
+/// ```no_run
+/// use tailgaters::{dish_controller::SerialSettings, Dish};
+///
+/// let dish = Dish::open("/dev/ttyACM0", SerialSettings::default())?;
+/// dish.move_to(160.2, 31.5)?;
+/// println!("{}", dish.read_rf()?);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Dish {
+    controller: Arc<Mutex<Box<dyn DishController>>>,
+    state: Arc<RwLock<DishState>>,
+    actions: ActionManager,
+    samples: crossbeam::channel::Receiver<RfPowerSample>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Dish {
+    /// Connects to the dish on a serial port.
+    pub fn open(port: &str, settings: SerialSettings) -> Result<Dish, DishError> {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let controller = DishSerialController::new(port, settings, tx.clone(), None, 0)?;
+        Ok(Dish::start(Box::new(controller), tx, rx))
+    }
+
+    /// A simulated dish with its signal peak at `peak`, see `DishSimulator`.
+    pub fn simulated(peak: DishPosition) -> Dish {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let controller = DishSimulator::new(peak, ElevationCalibration::default(), tx.clone());
+        Dish::start(Box::new(controller), tx, rx)
+    }
+
+    fn start(
+        controller: Box<dyn DishController>,
+        tx: crossbeam::channel::Sender<GlobalBus>,
+        rx: crossbeam::channel::Receiver<GlobalBus>,
+    ) -> Dish {
+        let controller = Arc::new(Mutex::new(controller));
+        let state = Arc::new(RwLock::new(DishState::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (samples_tx, samples) = crossbeam::channel::bounded(SAMPLES_KEPT);
+
+        let bus = BusThread {
+            rx,
+            controller: controller.clone(),
+            state: state.clone(),
+            samples: samples_tx,
+            stop: stop.clone(),
+            started_at: Instant::now(),
+        };
+        thread::spawn(move || bus.run());
+
+        let actions = ActionManager::new(
+            tx,
+            state.clone(),
+            Arc::new(AtomicBool::new(false)),
+            RF_WATCH_S,
+            MotionOptions::default(),
+        );
+        Dish {
+            controller,
+            state,
+            actions,
+            samples,
+            stop,
+        }
+    }
+
+    /// Moves to the position and waits until the readback got there, see
+    /// `ActionManager::set_position_blocking`.
+    pub fn move_to(&self, azimuth: f64, elevation: f64) -> color_eyre::Result<()> {
+        self.actions.set_position_blocking(azimuth, elevation)
+    }
+
+    /// Takes an rf reading where the dish is and waits for it.
+    pub fn read_rf(&self) -> Result<f64, DishError> {
+        let readings_before = self.state.read().unwrap().rf_sample_count;
+        let command = DishCommand::RfWatch(RF_WATCH_S);
+        self.controller
+            .lock()
+            .unwrap()
+            .send_command(command.clone())?;
+
+        let deadline = Instant::now() + Duration::from_secs(RF_WATCH_S as u64) + RF_REPLY_MARGIN;
+        while Instant::now() < deadline {
+            let state = self.state.read().unwrap().snapshot();
+            if state.rf_sample_count > readings_before {
+                return Ok(state.signal_strength);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        Err(DishError::Timeout(command))
+    }
+
+    /// Every rf reading the dish sends, with the position it was taken at,
+    /// whoever asked for it.
+    pub fn samples(&self) -> crossbeam::channel::Receiver<RfPowerSample> {
+        self.samples.clone()
+    }
+
+    /// azimuth, elevation of the last readback
+    pub fn position(&self) -> (f64, f64) {
+        let state = self.state.read().unwrap().snapshot();
+        (state.azimuth_angle, state.elevation_angle)
+    }
+
+    /// The shared state, for everything `position` doesn't cover.
+    pub fn state(&self) -> Arc<RwLock<DishState>> {
+        self.state.clone()
+    }
+}
+
+impl Drop for Dish {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// What the app's main loop does for a dish, without the UI.
+struct BusThread {
+    rx: crossbeam::channel::Receiver<GlobalBus>,
+    controller: Arc<Mutex<Box<dyn DishController>>>,
+    state: Arc<RwLock<DishState>>,
+    samples: crossbeam::channel::Sender<RfPowerSample>,
+    stop: Arc<AtomicBool>,
+    started_at: Instant,
+}
+
+impl BusThread {
+    fn run(self) {
+        while !self.stop.load(Ordering::Relaxed) {
+            let event = match self.rx.recv_timeout(STOP_CHECK) {
+                Ok(event) => event,
+                Err(crossbeam::channel::RecvTimeoutError::Timeout) => continue,
+                Err(crossbeam::channel::RecvTimeoutError::Disconnected) => break,
+            };
+            match event {
+                GlobalBus::DishCommand(command) | GlobalBus::PollCommand(_, command) => {
+                    self.send(command)
+                }
+                GlobalBus::DishResponse(_, response) => self.handle_response(response),
+                event => debug!("Not handled without the app: {:?}", event),
+            }
+        }
+    }
+
+    fn send(&self, command: DishCommand) {
+        let command = {
+            let state = self.state.read().unwrap();
+            state
+                .soft_limits
+                .clamp(command, &state.elevation_calibration)
+        };
+        if let Err(e) = self.controller.lock().unwrap().send_command(command) {
+            error!("Could not send command to the dish: {}", e);
+        }
+    }
+
+    fn handle_response(&self, response: DishResponse) {
+        let mut state = self.state.write().unwrap();
+        state.update_from_response(&response);
+        if let DishResponse::RfPower(power) = response {
+            let sample = RfPowerSample {
+                power,
+                azimuth: state.azimuth_angle,
+                elevation: state.elevation_angle,
+                elapsed: self.started_at.elapsed(),
+                timestamp: SystemTime::now(),
+                target: None,
+                power_stddev: None,
+            };
+            // nobody is reading them if the channel is full
+            let _ = self.samples.try_send(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulated_dish() {
+        let peak = DishPosition {
+            azimuth: 182.0,
+            elevation: 1.0,
+        };
+        let dish = Dish::simulated(peak);
+        let samples = dish.samples();
+
+        dish.move_to(peak.azimuth, peak.elevation).unwrap();
+        let (azimuth, elevation) = dish.position();
+        assert!((azimuth - peak.azimuth).abs() < 2.0 && (elevation - peak.elevation).abs() < 2.0);
+
+        // well above the simulated noise floor of 3100
+        let power = dish.read_rf().unwrap();
+        assert!(power > 3500.0, "{}", power);
+        assert_eq!(samples.try_recv().unwrap().power, power);
+    }
+}
@@ -1,147 +1,1593 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
-use log::{info, warn};
+use color_eyre::{eyre::eyre, Result};
+use log::{debug, error, info, warn};
 
-use crate::{dish_controller::DishState, dish_driver::DishCommand, GlobalBus};
+use crate::{
+    dish_controller::{
+        azimuth_distance, DishSnapshot, DishState, ElevationCalibration, PointReadings,
+        AZIMUTH_ANGLE_MAX,
+    },
+    dish_driver::DishCommand,
+    npy, GlobalBus,
+};
 
 #[derive(Debug)]
 pub struct Sweep1DParams {
     pub start: i32,
     pub end: i32,
     pub step: i32,
+    /// time to wait for the rf reading at each point
+    pub dwell_ms: u64,
 }
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Scan2DParams {
     pub bottom_left: DishPosition,
     pub top_right: DishPosition,
     pub step: f64,
+    /// time to wait for the rf reading at each point
+    pub dwell_ms: u64,
+    /// plan target being scanned, recorded with each sample
+    pub name: Option<String>,
+    /// end the scan at the first reading at least this strong, leaving the
+    /// dish pointed there
+    pub stop_on_power: Option<f64>,
+    /// rf readings averaged into the sample of each point, so one noisy
+    /// reading doesn't look like a peak
+    pub samples_per_point: u32,
 }
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct DishPosition {
     pub azimuth: f64,
     pub elevation: f64,
 }
 
+/// Where the dish is on the ground
+#[derive(Clone, Copy, Debug)]
+pub struct GpsLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// equatorial radius of the earth (WGS 84)
+const EARTH_RADIUS_KM: f64 = 6378.137;
+/// radius of the geostationary orbit, from the center of the earth
+const GEOSTATIONARY_RADIUS_KM: f64 = 42164.0;
+
+impl GpsLocation {
+    /// Azimuth (clockwise from true north) and elevation of a geostationary
+    /// satellite at the given longitude, seen from here. None if it's below
+    /// the horizon. The earth is taken as a sphere, which is off by less than
+    /// a tenth of a degree.
+    pub fn geostationary_look_angles(&self, satellite_longitude: f64) -> Option<DishPosition> {
+        let (lat, lon) = (self.latitude.to_radians(), self.longitude.to_radians());
+        let sat_lon = satellite_longitude.to_radians();
+
+        // earth centered vectors to the site and to the satellite
+        let site = [
+            EARTH_RADIUS_KM * lat.cos() * lon.cos(),
+            EARTH_RADIUS_KM * lat.cos() * lon.sin(),
+            EARTH_RADIUS_KM * lat.sin(),
+        ];
+        let satellite = [
+            GEOSTATIONARY_RADIUS_KM * sat_lon.cos(),
+            GEOSTATIONARY_RADIUS_KM * sat_lon.sin(),
+            0.0,
+        ];
+        let d: Vec<f64> = (0..3).map(|i| satellite[i] - site[i]).collect();
+
+        // the same vector in the local east/north/up frame
+        let east = -lon.sin() * d[0] + lon.cos() * d[1];
+        let north = -lat.sin() * lon.cos() * d[0] - lat.sin() * lon.sin() * d[1] + lat.cos() * d[2];
+        let up = lat.cos() * lon.cos() * d[0] + lat.cos() * lon.sin() * d[1] + lat.sin() * d[2];
+
+        let elevation = up.atan2(east.hypot(north)).to_degrees();
+        if elevation < 0.0 {
+            return None;
+        }
+        Some(DishPosition {
+            azimuth: east.atan2(north).to_degrees().rem_euclid(AZIMUTH_ANGLE_MAX),
+            elevation,
+        })
+    }
+}
+
+impl DishPosition {
+    /// Rejects positions the motors can't reach, so a typo doesn't stall them at a stop.
+    /// The position is in sky coordinates, the reachable ones are those
+    /// within both the calibration and the soft limits, shifted by the
+    /// `state`'s pointing offset.
+    pub fn validate(&self, state: &DishState) -> Result<()> {
+        let (el_min, el_max) = reachable_elevations(state);
+        if !(0.0..=AZIMUTH_ANGLE_MAX).contains(&self.azimuth) {
+            return Err(eyre!(
+                "azimuth {} is out of range, must be within 0..{}",
+                self.azimuth,
+                AZIMUTH_ANGLE_MAX
+            ));
+        }
+        let (az_min, az_max) = state.soft_limits.azimuth;
+        let dish_azimuth = state.dish_azimuth(self.azimuth);
+        if !(az_min..=az_max).contains(&dish_azimuth) {
+            return Err(eyre!(
+                "azimuth {} is {:.2} on the dish, outside the soft limits {}..{}",
+                self.azimuth,
+                dish_azimuth,
+                az_min,
+                az_max
+            ));
+        }
+        if !(el_min..=el_max).contains(&self.elevation) {
+            return Err(eyre!(
+                "elevation {} is out of range, must be within {}..{}",
+                self.elevation,
+                el_min,
+                el_max
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// the sky elevations within both the calibration and the soft limits
+fn reachable_elevations(state: &DishState) -> (f64, f64) {
+    let (cal_min, cal_max) = state.elevation_calibration.angle_limits();
+    let (soft_min, soft_max) = state.soft_limits.elevation;
+    (
+        cal_min.max(soft_min) + state.pointing_offset.elevation,
+        cal_max.min(soft_max) + state.pointing_offset.elevation,
+    )
+}
+
+/// `<azimuth>,<elevation>` in degrees, e.g. `160.2,31.5`
+impl std::str::FromStr for DishPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (azimuth, elevation) = s
+            .split_once(',')
+            .ok_or_else(|| format!("expected <azimuth>,<elevation>, got {:?}", s))?;
+        let angle = |value: &str| {
+            value
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| format!("{:?}: {}", value, e))
+        };
+        Ok(DishPosition {
+            azimuth: angle(azimuth)?,
+            elevation: angle(elevation)?,
+        })
+    }
+}
+
+impl Sweep1DParams {
+    /// The step can be negative for a top-down sweep, but has to lead from
+    /// `start` towards `end`.
+    pub fn validate(&self) -> Result<()> {
+        if self.step == 0 {
+            return Err(eyre!("sweep step can't be 0"));
+        }
+        if (self.end - self.start).signum() * self.step.signum() < 0 {
+            return Err(eyre!(
+                "sweep step {} leads away from {} towards {}",
+                self.step,
+                self.start,
+                self.end
+            ));
+        }
+        Ok(())
+    }
+
+    /// elevations from `start` to `end` (inclusive if a step lands on it), in
+    /// either direction
+    fn points(&self) -> Vec<i32> {
+        let count = (self.end - self.start) / self.step;
+        (0..=count).map(|i| self.start + i * self.step).collect()
+    }
+}
+
+impl Scan2DParams {
+    pub fn validate(&self, state: &DishState) -> Result<()> {
+        self.bottom_left.validate(state)?;
+        self.top_right.validate(state)?;
+        if !(self.step.is_finite() && self.step > 0.0) {
+            return Err(eyre!("scan step must be positive, got {}", self.step));
+        }
+        if self.samples_per_point == 0 {
+            return Err(eyre!("a scan point needs at least one sample"));
+        }
+        Ok(())
+    }
+
+    /// The scan grid in the order it's visited: one azimuth column after
+    /// the other, the elevation going up the first column, down the next and
+    /// so on, so the dish never drives back across the whole range between
+    /// columns. The top and right bounds are exclusive.
+    pub fn points(&self) -> Vec<DishPosition> {
+        let (az_points, el_points) = self.axes();
+        let mut points = Vec::with_capacity(az_points.len() * el_points.len());
+        for (column, &azimuth) in az_points.iter().enumerate() {
+            let column_points = el_points
+                .iter()
+                .map(|&elevation| DishPosition { azimuth, elevation });
+            if column % 2 == 0 {
+                points.extend(column_points);
+            } else {
+                points.extend(column_points.rev());
+            }
+        }
+        points
+    }
+
+    /// azimuths and elevations of the grid, each stepping up from the bottom
+    /// left bound to just short of the top right one. An axis whose start
+    /// isn't below its end is empty, there's no counting down.
+    pub fn axes(&self) -> (Vec<f64>, Vec<f64>) {
+        (
+            axis_points(self.bottom_left.azimuth, self.top_right.azimuth, self.step),
+            axis_points(
+                self.bottom_left.elevation,
+                self.top_right.elevation,
+                self.step,
+            ),
+        )
+    }
+}
+
 #[derive(Debug)]
 pub enum DishAction {
     ElevationSweep(Sweep1DParams),
     Scan2d(Scan2DParams),
     MoveAngles(f64, f64),
+    /// Move by an offset from the current position. The azimuth wraps around,
+    /// the elevation is clamped to the range the motors can reach and an
+    /// azimuth past the soft limits is refused.
+    MoveRelative {
+        d_az: f64,
+        d_el: f64,
+    },
+    /// Keep the dish peaked on a signal by dithering around the current position
+    /// and moving towards the stronger side, until cancelled.
+    StepTrack {
+        dither_deg: f64,
+        period_ms: u64,
+    },
+    /// Park the dish at the lower elevation stop, e.g. before transporting it.
+    Stow {
+        azimuth: f64,
+    },
+    /// End of session: let the rf reading finish, stow, optionally reset the
+    /// firmware, then quit the app.
+    Shutdown {
+        stow_azimuth: f64,
+        reset: bool,
+    },
+    /// Drive the elevation into both mechanical stops and use the readbacks
+    /// there as the new elevation calibration, for units with different stops.
+    CalibrateElevationRange,
+    /// Take one rf reading at each of the positions, in order.
+    Waypoints(Vec<DishPosition>),
+    /// Quick peaking: read every `step` degrees within ±`box_deg` of the
+    /// current position and move to the strongest point.
+    LocalPeak {
+        box_deg: f64,
+        step: f64,
+    },
+    /// Keep the dish where it is against drift, commanding the position
+    /// again whenever the readback wanders off, until cancelled.
+    Hold,
+    /// Move to `position` once, then take an rf reading every `interval_ms`
+    /// for `duration`, or until cancelled if None. For studying fades at a
+    /// fixed pointing.
+    Monitor {
+        position: DishPosition,
+        duration: Option<std::time::Duration>,
+        interval_ms: u64,
+    },
+    /// From a peak at `start`, take a reading every `step` degrees of
+    /// azimuth outwards on both sides until the power falls below half of
+    /// the peak above the noise floor (-3 dB), and log the width between
+    /// those points.
+    MeasureBeamwidth {
+        start: DishPosition,
+        step: f64,
+    },
 }
 
+/// Where the power falls through `threshold` between the last reading above
+/// it and the first below, interpolating linearly; each is (offset, power).
+fn half_power_offset(inside: (f64, f64), outside: (f64, f64), threshold: f64) -> f64 {
+    let (offset_in, power_in) = inside;
+    let (offset_out, power_out) = outside;
+    offset_in + (offset_out - offset_in) * (power_in - threshold) / (power_in - power_out)
+}
+
+/// Points from `start` towards `end` (exclusive) spaced by `step`.
+///
+/// Computed by index rather than by accumulation, so fractional steps
+/// don't drift away from the grid over long scans.
+fn axis_points(start: f64, end: f64, step: f64) -> Vec<f64> {
+    let mut points = vec![];
+    let mut index = 0;
+    loop {
+        let point = start + index as f64 * step;
+        if point >= end {
+            break;
+        }
+        points.push(point);
+        index += 1;
+    }
+    points
+}
+
+/// How the blocking moves drive the motors.
+#[derive(Clone, Copy, Debug)]
+pub struct MotionOptions {
+    /// hold the elevation with `elevmt` instead of a one-shot `elangle`, which
+    /// can lose the position under wind load
+    pub elevation_maintain: bool,
+    /// rotate with `azim` instead of `azangle`, see `DishCommand::SetAzimuthHeading`
+    pub azimuth_heading: bool,
+    /// move the elevation by count with `elev` instead of `elangle`, see
+    /// `DishCommand::SetElevationCount`
+    pub elevation_counts: bool,
+    /// how close to the target, in degrees on both axes, counts as arrived
+    pub tolerance_deg: f64,
+    /// how long to wait for the dish to arrive before sending the move again
+    pub timeout_ms: u64,
+    /// moves sent again after the first one timed out, before giving up
+    pub retries: u32,
+    /// before each retry, nudge the axes that didn't arrive away from the
+    /// target to break the static friction of a stuck gear
+    pub rehome: bool,
+}
+
+impl Default for MotionOptions {
+    fn default() -> Self {
+        MotionOptions {
+            elevation_maintain: false,
+            azimuth_heading: false,
+            elevation_counts: false,
+            tolerance_deg: 2.0,
+            timeout_ms: 15_000,
+            retries: 1,
+            rehome: false,
+        }
+    }
+}
+
+impl MotionOptions {
+    /// the command moving the azimuth to a sky angle
+    pub fn azimuth_command(&self, state: &DishState, angle: f64) -> DishCommand {
+        let angle = state.firmware_azimuth(angle);
+        if self.azimuth_heading {
+            DishCommand::SetAzimuthHeading(angle)
+        } else {
+            DishCommand::SetAzimuthAngle(angle)
+        }
+    }
+
+    /// the command moving the elevation to a sky angle
+    pub fn elevation_command(&self, state: &DishState, angle: f64) -> DishCommand {
+        if self.elevation_maintain {
+            DishCommand::SetElevationMaintain(state.elevation_angle_to_count(angle))
+        } else if self.elevation_counts {
+            DishCommand::SetElevationCount(state.elevation_angle_to_count(angle))
+        } else {
+            DishCommand::SetElevationAngle(state.dish_elevation(angle))
+        }
+    }
+}
+
+/// time to wait for an rf reading while tracking
+const TRACK_DWELL_MS: u64 = 1000;
+/// counts of power the two sides of a track dither must differ by before
+/// the dish is moved, closer than this is noise
+const TRACK_DEADBAND: f64 = 10.0;
+/// how close to the lower stop the elevation readback must get to count as stowed
+const STOW_COUNT_TOLERANCE: i32 = 5;
+const STOW_TIMEOUT_MS: u64 = 10_000;
+/// time given to the firmware to reboot before quitting
+const RESET_DELAY_MS: u64 = 2000;
+/// time for a nudge to show up in the elevation readback
+const NUDGE_SETTLE_MS: u64 = 300;
+/// nudges in a row that barely move the readback before the motor counts as
+/// stopped at the stop
+const STOP_NUDGES: u32 = 3;
+const STOP_COUNT_TOLERANCE: i32 = 1;
+/// gives up looking for a stop after this many nudges
+const MAX_CALIBRATION_NUDGES: u32 = 1000;
+/// how often the position is checked while holding
+const HOLD_PERIOD_MS: u64 = 2000;
+/// how far from the peak a beamwidth measurement looks for the half power
+/// points before giving up on that side
+const BEAMWIDTH_MAX_DEG: f64 = 20.0;
+/// drift, in degrees on either axis, after which a hold commands the position
+/// again; about a nudge
+const HOLD_TOLERANCE_DEG: f64 = 0.2;
+/// how often the counts are compared while waiting for the motors to settle
+const SETTLE_POLL_MS: u64 = 200;
+/// polls in a row the counts must stay within `SETTLE_COUNT_TOLERANCE` of
+/// each other for the dish to count as settled
+const SETTLE_POLLS: u32 = 3;
+const SETTLE_COUNT_TOLERANCE: i32 = 2;
+/// longest wait for the motors to settle after a move, a dish swaying in the
+/// wind never does
+const SETTLE_TIMEOUT_MS: u64 = 3000;
+/// how long each self-test check waits for the dish to answer or move
+const SELFTEST_TIMEOUT_MS: u64 = 3000;
+
 pub struct ActionManager {
     tx_channel: crossbeam::channel::Sender<GlobalBus>,
     state: std::sync::Arc<std::sync::RwLock<DishState>>,
+    cancel: Arc<AtomicBool>,
+    /// seconds of rf readings averaged into each measurement
+    rf_watch: u32,
+    motion: MotionOptions,
 }
 
 impl ActionManager {
     pub fn new(
         tx_channel: crossbeam::channel::Sender<GlobalBus>,
         state: Arc<std::sync::RwLock<DishState>>,
+        cancel: Arc<AtomicBool>,
+        rf_watch: u32,
+        motion: MotionOptions,
     ) -> ActionManager {
-        ActionManager { tx_channel, state }
+        ActionManager {
+            tx_channel,
+            state,
+            cancel,
+            rf_watch,
+            motion,
+        }
+    }
+
+    fn azimuth_command(&self, angle: f64) -> DishCommand {
+        self.motion
+            .azimuth_command(&self.state.read().unwrap(), angle)
+    }
+
+    fn elevation_command(&self, angle: f64) -> DishCommand {
+        self.motion
+            .elevation_command(&self.state.read().unwrap(), angle)
+    }
+
+    /// asks for an rf reading, returning how long to wait for it: at least
+    /// `dwell_ms`, but never less than the averaging window
+    fn request_rf(&self, dwell_ms: u64) -> std::time::Duration {
+        self.send(GlobalBus::DishCommand(DishCommand::RfWatch(self.rf_watch)));
+        std::time::Duration::from_millis(dwell_ms.max(self.rf_watch as u64 * 1000))
+    }
+
+    /// the state as of now, without holding its lock while looking at it
+    fn snapshot(&self) -> DishSnapshot {
+        self.state.read().unwrap().snapshot()
+    }
+
+    /// puts a message on the bus; if the app is shutting down nobody is
+    /// listening anymore and the message is dropped
+    fn send(&self, message: GlobalBus) {
+        if let Err(e) = self.tx_channel.send(message) {
+            debug!("Bus closed, dropping {:?}", e.into_inner());
+        }
+    }
+
+    /// true if the user asked to abort the running action
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// sleeps for `ms`, waking up early if the action gets cancelled.
+    /// Returns false if it was cancelled.
+    fn sleep_cancellable(&self, ms: u64) -> bool {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_millis(ms);
+        while std::time::Instant::now() < deadline {
+            if self.cancelled() {
+                return false;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        true
+    }
+
+    /// asks for an rf reading and waits `dwell_ms` for it, or for the whole
+    /// reading if that takes longer. Returns false if the action got
+    /// cancelled meanwhile.
+    fn dwell(&self, dwell_ms: u64) -> bool {
+        let wait = self.request_rf(dwell_ms);
+        self.sleep_cancellable(wait.as_millis() as u64)
+    }
+
+    /// moves to the given position and takes a single rf reading there, None
+    /// if the dish didn't get there or no new reading arrived
+    fn measure_rf_at(&self, az: f64, el: f64) -> Option<f64> {
+        if let Err(e) = self.set_position_blocking(az, el) {
+            warn!("Not measuring at {:.2}/{:.2}: {}", az, el, e);
+            return None;
+        }
+        self.measure_point(TRACK_DWELL_MS, 1)
+    }
+
+    /// Takes the readings of a scan point where the dish is, waiting at least
+    /// `dwell_ms` for each. With more than one they're recorded as a single
+    /// sample of their mean. Returns the power of the point, None if the
+    /// readings didn't all arrive.
+    fn measure_point(&self, dwell_ms: u64, samples: u32) -> Option<f64> {
+        let readings_before = self.snapshot().rf_sample_count;
+        if samples <= 1 {
+            if !self.dwell(dwell_ms) {
+                return None;
+            }
+            let state = self.snapshot();
+            return (state.rf_sample_count > readings_before).then_some(state.signal_strength);
+        }
+
+        self.state.write().unwrap().point_readings = Some(PointReadings::new(samples));
+        let mut cancelled = false;
+        for _ in 0..samples {
+            if !self.dwell(dwell_ms) {
+                cancelled = true;
+                break;
+            }
+        }
+        let point = self.state.write().unwrap().point_readings.take()?;
+        if cancelled {
+            return None;
+        }
+        if point.readings.len() < samples as usize {
+            warn!(
+                "Only {} of {} readings arrived, point not recorded",
+                point.readings.len(),
+                samples
+            );
+            return None;
+        }
+        Some(point.mean_stddev().0)
+    }
+
+    fn report_progress(&self, done: usize, total: usize) {
+        self.send(GlobalBus::ActionProgress { done, total });
     }
 
     pub fn render(&self, action: DishAction) {
+        // a cancel request only applies to the action that was running when it was made
+        self.cancel.store(false, Ordering::Relaxed);
+
         match action {
             DishAction::ElevationSweep(params) => {
-                let mut current = params.start;
-                while current <= params.end {
-                    self.tx_channel
-                        .send(GlobalBus::DishCommand(DishCommand::SetElevationAngle(
-                            current as f64,
-                        )))
-                        .unwrap();
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                    self.tx_channel
-                        .send(GlobalBus::DishCommand(DishCommand::RfWatch(1)))
-                        .unwrap();
-                    std::thread::sleep(std::time::Duration::from_millis(1000));
-                    current += params.step;
+                if let Err(e) = params.validate() {
+                    error!("Invalid sweep parameters, aborting sweep: {}", e);
+                    return;
+                }
+
+                for elevation in params.points() {
+                    if self.cancelled() {
+                        warn!("Elevation sweep cancelled");
+                        break;
+                    }
+                    self.send(GlobalBus::DishCommand(
+                        self.elevation_command(elevation as f64),
+                    ));
+                    self.wait_until_settled(std::time::Duration::from_millis(SETTLE_TIMEOUT_MS));
+                    self.dwell(params.dwell_ms);
                 }
             }
             DishAction::MoveAngles(az, el) => {
-                self.set_position_blocking(az, el);
+                if let Err(e) = self.set_position_blocking(az, el) {
+                    error!("Move failed: {}", e);
+                }
+            }
+            DishAction::MoveRelative { d_az, d_el } => {
+                let (target, el, validated) = {
+                    let state = self.state.read().unwrap();
+                    let (el_min, el_max) = reachable_elevations(&state);
+                    let el = state.elevation_angle + d_el;
+                    let target = DishPosition {
+                        azimuth: (state.azimuth_angle + d_az).rem_euclid(AZIMUTH_ANGLE_MAX),
+                        elevation: el.clamp(el_min, el_max),
+                    };
+                    (target, el, target.validate(&state))
+                };
+                if let Err(e) = validated {
+                    error!("Relative move refused: {}", e);
+                    return;
+                }
+                if target.elevation != el {
+                    warn!(
+                        "Relative move clamped from elevation {:.2} to {:.2}",
+                        el, target.elevation
+                    );
+                }
+
+                if let Err(e) = self.set_position_blocking(target.azimuth, target.elevation) {
+                    error!("Relative move failed: {}", e);
+                }
             }
             DishAction::Scan2d(params) => {
-                info!("Starting scan");
-
-                for az in (params.bottom_left.azimuth as i32..params.top_right.azimuth as i32)
-                    .step_by(params.step as usize)
-                {
-                    for el in (params.bottom_left.elevation as i32
-                        ..params.top_right.elevation as i32)
-                        .step_by(params.step as usize)
-                    {
-                        self.set_position_blocking(az as f64, el as f64);
-                        self.tx_channel
-                            .send(GlobalBus::DishCommand(DishCommand::RfWatch(1)))
-                            .unwrap();
-                        std::thread::sleep(std::time::Duration::from_millis(1000));
+                let validated = params.validate(&self.state.read().unwrap());
+                if let Err(e) = validated {
+                    error!("Invalid scan parameters, aborting scan: {}", e);
+                    return;
+                }
+
+                match &params.name {
+                    Some(name) => info!("Starting scan of {}", name),
+                    None => info!("Starting scan"),
+                }
+                self.state.write().unwrap().target = params.name.clone();
+
+                let points = params.points();
+                let mut stopped_on_power = false;
+                self.report_progress(0, points.len());
+                let (azimuths, elevations) = params.axes();
+                self.send(GlobalBus::ScanStarted(
+                    params.name.clone(),
+                    Some(npy::ScanGrid::new(azimuths, elevations)),
+                ));
+
+                for (i, point) in points.iter().enumerate() {
+                    if self.cancelled() {
+                        warn!("Scan cancelled, returning to start position");
+                        break;
+                    }
+
+                    let (az, el) = (point.azimuth, point.elevation);
+                    let power = match self.set_position_blocking(az, el) {
+                        Ok(()) => self.measure_point(params.dwell_ms, params.samples_per_point),
+                        // a reading taken elsewhere would land on the wrong point
+                        Err(e) => {
+                            warn!("Skipping point: {}", e);
+                            None
+                        }
+                    };
+
+                    self.report_progress(i + 1, points.len());
+
+                    if let (Some(threshold), Some(power)) = (params.stop_on_power, power) {
+                        if power >= threshold {
+                            info!(
+                                "Power {} reached {} at azimuth: {:.2}, elevation: {:.2}, stopping the scan here",
+                                power, threshold, az, el
+                            );
+                            stopped_on_power = true;
+                            break;
+                        }
                     }
                 }
 
                 info!("Scan finished!!");
 
-                self.set_position_blocking(
-                    params.bottom_left.azimuth,
-                    params.bottom_left.elevation,
-                );
+                if !stopped_on_power {
+                    if let Err(e) = self.set_position_blocking(
+                        params.bottom_left.azimuth,
+                        params.bottom_left.elevation,
+                    ) {
+                        warn!("Could not return to the start position: {}", e);
+                    }
+                }
+
+                self.state.write().unwrap().target = None;
+                self.send(GlobalBus::ScanFinished);
 
                 info!("Exiting scan");
             }
+            DishAction::Waypoints(points) => self.visit_waypoints(&points),
+            DishAction::LocalPeak { box_deg, step } => self.local_peak(box_deg, step),
+            DishAction::Hold => self.hold(),
+            DishAction::Monitor {
+                position,
+                duration,
+                interval_ms,
+            } => self.monitor(position, duration, interval_ms),
+            DishAction::MeasureBeamwidth { start, step } => self.measure_beamwidth(start, step),
+            DishAction::Stow { azimuth } => {
+                self.stow(azimuth);
+            }
+            DishAction::Shutdown {
+                stow_azimuth,
+                reset,
+            } => {
+                info!("Shutting down: waiting for the last rf reading");
+                // the firmware can't abort an rfwatch, let the last one run out
+                if !self.sleep_cancellable(self.rf_watch as u64 * 1000) {
+                    warn!("Shutdown cancelled");
+                    return;
+                }
+
+                info!("Shutting down: stowing");
+                if !self.stow(stow_azimuth) {
+                    if self.cancelled() {
+                        warn!("Shutdown cancelled");
+                    } else {
+                        error!("Not shutting down with the dish unstowed");
+                    }
+                    return;
+                }
+
+                if reset {
+                    info!("Shutting down: resetting the firmware");
+                    self.send(GlobalBus::DishCommand(DishCommand::Reset));
+                    std::thread::sleep(std::time::Duration::from_millis(RESET_DELAY_MS));
+                }
+
+                info!("Shutdown sequence finished");
+                self.send(GlobalBus::Quit);
+            }
+            DishAction::StepTrack {
+                dither_deg,
+                period_ms,
+            } => {
+                let (mut az, mut el) = {
+                    let state = self.state.read().unwrap();
+                    (state.azimuth_angle, state.elevation_angle)
+                };
+                info!(
+                    "Starting step track at azimuth: {:.2}, elevation: {:.2}",
+                    az, el
+                );
+
+                while !self.cancelled() {
+                    let readings = [
+                        self.measure_rf_at((az + dither_deg).rem_euclid(AZIMUTH_ANGLE_MAX), el),
+                        self.measure_rf_at((az - dither_deg).rem_euclid(AZIMUTH_ANGLE_MAX), el),
+                        self.measure_rf_at(az, el + dither_deg),
+                        self.measure_rf_at(az, el - dither_deg),
+                    ];
+                    let [Some(az_plus), Some(az_minus), Some(el_plus), Some(el_minus)] = readings
+                    else {
+                        if !self.cancelled() {
+                            warn!("Track readings missing, no correction this time");
+                        }
+                        if !self.sleep_cancellable(period_ms) {
+                            break;
+                        }
+                        continue;
+                    };
+
+                    // step half a dither towards whichever side is clearly stronger
+                    let step = |plus: f64, minus: f64| {
+                        if (plus - minus).abs() <= TRACK_DEADBAND {
+                            0.0
+                        } else {
+                            (plus - minus).signum() * dither_deg / 2.0
+                        }
+                    };
+                    let d_az = step(az_plus, az_minus);
+                    let d_el = step(el_plus, el_minus);
+                    let next = DishPosition {
+                        azimuth: (az + d_az).rem_euclid(AZIMUTH_ANGLE_MAX),
+                        elevation: el + d_el,
+                    };
+                    let validated = next.validate(&self.state.read().unwrap());
+                    if let Err(e) = validated {
+                        warn!("Not following the track further: {}", e);
+                        if !self.sleep_cancellable(period_ms) {
+                            break;
+                        }
+                        continue;
+                    }
+                    az = next.azimuth;
+                    el = next.elevation;
+
+                    info!(
+                        "Track correction: az {:+.2} el {:+.2}, now at {:.2}/{:.2}",
+                        d_az, d_el, az, el
+                    );
+                    if let Err(e) = self.set_position_blocking(az, el) {
+                        warn!("Track correction incomplete: {}", e);
+                    }
+
+                    if !self.sleep_cancellable(period_ms) {
+                        break;
+                    }
+                }
+
+                info!("Step track stopped");
+            }
+            DishAction::CalibrateElevationRange => self.calibrate_elevation_range(),
         }
     }
 
-    pub fn set_azimuth_blocking(&self, angle: f64) {
-        self.tx_channel
-            .send(GlobalBus::DishCommand(DishCommand::SetAzimuthAngle(angle)))
-            .unwrap();
+    fn calibrate_elevation_range(&self) {
+        let (azimuth, calibration) = {
+            let state = self.state.read().unwrap();
+            (state.azimuth_angle, state.elevation_calibration)
+        };
+        let (el_min, el_max) = calibration.angle_limits();
 
-        while (self.state.read().unwrap().azimuth_angle - angle).abs() > 0.1 {
-            std::thread::sleep(std::time::Duration::from_millis(100));
+        let mut stops = vec![];
+        for (name, angle, nudge) in [
+            ("lower", el_min, DishCommand::NudgeElevationDown),
+            ("upper", el_max, DishCommand::NudgeElevationUp),
+        ] {
+            info!("Calibrating elevation: looking for the {} stop", name);
+            // a regular move gets near the old stop quickly, the nudges do the rest
+            if let Err(e) = self.set_position_blocking(azimuth, angle) {
+                warn!("Move towards the {} stop incomplete: {}", name, e);
+            }
+            match self.find_elevation_stop(nudge) {
+                Some(count) => {
+                    info!("Found the {} elevation stop at count {}", name, count);
+                    stops.push(count);
+                }
+                None => {
+                    warn!("Elevation calibration aborted, keeping the old one");
+                    return;
+                }
+            }
+        }
+
+        let (count_min, count_max) = (stops[0], stops[1]);
+        let found = ElevationCalibration {
+            count_min,
+            count_max,
+            angle_max: calibration.angle_max,
+        };
+        if let Err(e) = found.validate() {
+            error!(
+                "Elevation stops make no sense ({}), keeping the old calibration",
+                e
+            );
+            return;
         }
+
+        self.state.write().unwrap().elevation_calibration = found;
+        info!(
+            "Elevation calibrated, add to the config: el_count_min = {}, el_count_max = {}",
+            count_min, count_max
+        );
     }
 
-    pub fn set_elevation_blocking(&self, angle: f64) {
-        self.tx_channel
-            .send(GlobalBus::DishCommand(DishCommand::SetElevationAngle(
-                angle,
-            )))
-            .unwrap();
+    /// Nudges the elevation until the readback stops changing, returning the
+    /// count there. None if cancelled or no stop was found.
+    fn find_elevation_stop(&self, nudge: DishCommand) -> Option<i32> {
+        let mut last = self.state.read().unwrap().elevation_count;
+        let mut still = 0;
+        for _ in 0..MAX_CALIBRATION_NUDGES {
+            self.send(GlobalBus::DishCommand(nudge.clone()));
+            if !self.sleep_cancellable(NUDGE_SETTLE_MS) {
+                return None;
+            }
 
-        while (self.state.read().unwrap().elevation_angle - angle).abs() > 0.1 {
-            std::thread::sleep(std::time::Duration::from_millis(100));
+            let count = self.state.read().unwrap().elevation_count;
+            if (count - last).abs() <= STOP_COUNT_TOLERANCE {
+                still += 1;
+                if still >= STOP_NUDGES {
+                    return Some(count);
+                }
+            } else {
+                still = 0;
+            }
+            last = count;
         }
+        None
     }
 
-    pub fn set_position_blocking(&self, az: f64, el: f64) {
-        self.tx_channel
-            .send(GlobalBus::DishCommand(DishCommand::SetAzimuthAngle(az)))
-            .unwrap();
-        self.tx_channel
-            .send(GlobalBus::DishCommand(DishCommand::SetElevationAngle(el)))
-            .unwrap();
+    fn visit_waypoints(&self, points: &[DishPosition]) {
+        let invalid = {
+            let state = self.state.read().unwrap();
+            points
+                .iter()
+                .enumerate()
+                .find_map(|(i, point)| point.validate(&state).err().map(|e| (i, e)))
+        };
+        if let Some((i, e)) = invalid {
+            error!("Invalid waypoint {}, aborting: {}", i + 1, e);
+            return;
+        }
+
+        info!("Visiting {} waypoints", points.len());
+        self.report_progress(0, points.len());
+        self.send(GlobalBus::ScanStarted(None, None));
 
-        let now = std::time::Instant::now();
-        while (self.state.read().unwrap().azimuth_angle - az).abs() > 2.0
-            || (self.state.read().unwrap().elevation_angle - el).abs() > 2.0
-        {
-            std::thread::sleep(std::time::Duration::from_millis(100));
-            if now.elapsed().as_secs() > 15 {
-                warn!("Timeout while setting position, position will be imprecise");
-                // try again
-                self.tx_channel
-                    .send(GlobalBus::DishCommand(DishCommand::SetAzimuthAngle(az)))
-                    .unwrap();
-                self.tx_channel
-                    .send(GlobalBus::DishCommand(DishCommand::SetElevationAngle(el)))
-                    .unwrap();
+        for (i, point) in points.iter().enumerate() {
+            if self.cancelled() {
+                warn!("Waypoints cancelled");
+                break;
+            }
+
+            match self.set_position_blocking(point.azimuth, point.elevation) {
+                // the shortest wait that still covers a whole reading
+                Ok(()) => {
+                    self.dwell(0);
+                }
+                Err(e) => warn!("Skipping waypoint {}: {}", i + 1, e),
+            }
+            self.report_progress(i + 1, points.len());
+        }
+
+        self.send(GlobalBus::ScanFinished);
+        info!("Waypoints done");
+    }
+
+    fn local_peak(&self, box_deg: f64, step: f64) {
+        if step <= 0.0 {
+            error!("Invalid peak search step {}, must be positive", step);
+            return;
+        }
+        // the box, inclusive of its edges, with the current position in the
+        // middle, leaving out what the dish can't reach
+        let offsets = axis_points(-box_deg, box_deg + step / 2.0, step);
+        let (az0, el0, points) = {
+            let state = self.state.read().unwrap();
+            let (az0, el0) = (state.azimuth_angle, state.elevation_angle);
+            let mut points = vec![];
+            for &d_az in &offsets {
+                for &d_el in &offsets {
+                    let point = DishPosition {
+                        azimuth: (az0 + d_az).rem_euclid(AZIMUTH_ANGLE_MAX),
+                        elevation: el0 + d_el,
+                    };
+                    if point.validate(&state).is_ok() {
+                        points.push((point.azimuth, point.elevation));
+                    }
+                }
+            }
+            (az0, el0, points)
+        };
+
+        info!(
+            "Looking for the peak within {}° of {:.2}/{:.2}, {} readings",
+            box_deg,
+            az0,
+            el0,
+            points.len() + 1
+        );
+        let Some(start_power) = self.measure_rf_at(az0, el0) else {
+            warn!("No reading at the start, not looking for the peak");
+            return;
+        };
+        let mut best = (start_power, az0, el0);
+        self.report_progress(0, points.len());
+
+        for (i, &(az, el)) in points.iter().enumerate() {
+            if self.cancelled() {
+                warn!("Peak search cancelled, going back to the start");
+                best = (start_power, az0, el0);
+                break;
+            }
+            // a point without its own reading can't be the peak
+            if let Some(power) = self.measure_rf_at(az, el) {
+                if power > best.0 {
+                    best = (power, az, el);
+                }
+            }
+            self.report_progress(i + 1, points.len());
+        }
+
+        let (power, az, el) = best;
+        if let Err(e) = self.set_position_blocking(az, el) {
+            warn!("Could not move to the peak: {}", e);
+        }
+        // no ratio to a start reading of nothing
+        let gain = if start_power > 0.0 {
+            format!(" ({:+.2} dB)", 10.0 * (power / start_power).log10())
+        } else {
+            String::new()
+        };
+        info!(
+            "Peak at {:.2}/{:.2}: {:.0}, {:+.0} counts{} from the start",
+            az,
+            el,
+            power,
+            power - start_power,
+            gain
+        );
+    }
+
+    fn hold(&self) {
+        let (az, el) = {
+            let mut state = self.state.write().unwrap();
+            state.hold = Some((state.azimuth_angle, state.elevation_angle));
+            (state.azimuth_angle, state.elevation_angle)
+        };
+        info!("Holding azimuth: {:.2}, elevation: {:.2}", az, el);
+
+        while self.sleep_cancellable(HOLD_PERIOD_MS) {
+            let DishSnapshot {
+                azimuth_angle: az_now,
+                elevation_angle: el_now,
+                ..
+            } = self.snapshot();
+            if azimuth_distance(az_now, az) > HOLD_TOLERANCE_DEG {
+                debug!(
+                    "Azimuth drifted to {:.2}, commanding {:.2} again",
+                    az_now, az
+                );
+                self.send(GlobalBus::DishCommand(self.azimuth_command(az)));
+            }
+            if (el_now - el).abs() > HOLD_TOLERANCE_DEG {
+                debug!(
+                    "Elevation drifted to {:.2}, commanding {:.2} again",
+                    el_now, el
+                );
+                self.send(GlobalBus::DishCommand(self.elevation_command(el)));
+            }
+        }
 
+        self.state.write().unwrap().hold = None;
+        info!("Hold released");
+    }
+
+    fn monitor(
+        &self,
+        position: DishPosition,
+        duration: Option<std::time::Duration>,
+        interval_ms: u64,
+    ) {
+        let validated = position.validate(&self.state.read().unwrap());
+        if let Err(e) = validated {
+            error!("Invalid monitor position, aborting: {}", e);
+            return;
+        }
+        if let Err(e) = self.set_position_blocking(position.azimuth, position.elevation) {
+            warn!("Monitoring away from the target: {}", e);
+        }
+        match duration {
+            Some(duration) => info!(
+                "Monitoring at {:.2}/{:.2} for {} s",
+                position.azimuth,
+                position.elevation,
+                duration.as_secs()
+            ),
+            None => info!(
+                "Monitoring at {:.2}/{:.2} until cancelled",
+                position.azimuth, position.elevation
+            ),
+        }
+
+        let start = std::time::Instant::now();
+        let mut readings = 0;
+        loop {
+            if let Some(duration) = duration {
+                let elapsed = start.elapsed();
+                self.report_progress(
+                    elapsed.as_secs().min(duration.as_secs()) as usize,
+                    duration.as_secs() as usize,
+                );
+                if elapsed >= duration {
+                    break;
+                }
+            }
+            let wait = self.request_rf(interval_ms);
+            if !self.sleep_cancellable(wait.as_millis() as u64) {
+                warn!("Monitor cancelled");
                 break;
             }
+            readings += 1;
+        }
+        info!("Monitor done after {} readings", readings);
+    }
+
+    fn measure_beamwidth(&self, start: DishPosition, step: f64) {
+        let validated = start.validate(&self.state.read().unwrap());
+        if let Err(e) = validated {
+            error!("Invalid beamwidth start, aborting: {}", e);
+            return;
+        }
+        if step <= 0.0 {
+            error!("Invalid beamwidth step {}, must be positive", step);
+            return;
+        }
+
+        let Some(peak) = self.measure_rf_at(start.azimuth, start.elevation) else {
+            error!("No reading at the peak, not measuring the beamwidth");
+            return;
+        };
+        let floor = match self.snapshot().noise_floor {
+            Some(floor) => Some(floor),
+            None => {
+                info!(
+                    "No noise floor estimate yet, reading it {}° off the peak",
+                    BEAMWIDTH_MAX_DEG
+                );
+                let azimuth = (start.azimuth + BEAMWIDTH_MAX_DEG).rem_euclid(AZIMUTH_ANGLE_MAX);
+                self.measure_rf_at(azimuth, start.elevation)
+            }
+        };
+        let Some(floor) = floor else {
+            error!("No reading of the noise floor, not measuring the beamwidth");
+            return;
+        };
+        if floor >= peak {
+            error!(
+                "No signal above the floor of {:.0} at {:.2}/{:.2}, not measuring the beamwidth",
+                floor, start.azimuth, start.elevation
+            );
+            return;
+        }
+        let half_power = floor + (peak - floor) / 2.0;
+        info!(
+            "Measuring the beamwidth at {:.2}/{:.2}: peak {:.0}, floor {:.0}, half power {:.0}",
+            start.azimuth, start.elevation, peak, floor, half_power
+        );
+
+        // offset of the half power point on each side, left then right
+        let mut edges = [None, None];
+        for (edge, direction) in edges.iter_mut().zip([-1.0, 1.0]) {
+            let mut inside = (0.0, peak);
+            for offset in axis_points(step, BEAMWIDTH_MAX_DEG + step / 2.0, step) {
+                if self.cancelled() {
+                    warn!("Beamwidth measurement cancelled");
+                    return;
+                }
+                let azimuth = (start.azimuth + direction * offset).rem_euclid(AZIMUTH_ANGLE_MAX);
+                let Some(power) = self.measure_rf_at(azimuth, start.elevation) else {
+                    continue;
+                };
+                if power < half_power {
+                    *edge = Some(half_power_offset(inside, (offset, power), half_power));
+                    break;
+                }
+                inside = (offset, power);
+            }
+        }
+
+        if let Err(e) = self.set_position_blocking(start.azimuth, start.elevation) {
+            warn!("Could not move back to the peak: {}", e);
+        }
+        let [Some(left), Some(right)] = edges else {
+            warn!(
+                "No half power point within {}° of the peak on the {} side",
+                BEAMWIDTH_MAX_DEG,
+                if edges[0].is_none() { "left" } else { "right" }
+            );
+            return;
+        };
+        let width = left + right;
+        info!(
+            "Beamwidth {:.2}° of azimuth ({:.2}° on the sky), half power at {:.2} and {:.2}, centered {:+.2}° from the start",
+            width,
+            width * start.elevation.to_radians().cos(),
+            (start.azimuth - left).rem_euclid(AZIMUTH_ANGLE_MAX),
+            (start.azimuth + right).rem_euclid(AZIMUTH_ANGLE_MAX),
+            (right - left) / 2.0
+        );
+    }
+
+    /// Parks the dish at the lower elevation stop, returning whether the
+    /// elevation readback confirmed it got there.
+    fn stow(&self, azimuth: f64) -> bool {
+        let (calibration, elevation) = {
+            let state = self.state.read().unwrap();
+            let calibration = state.elevation_calibration;
+            (
+                calibration,
+                state.elevation_count_to_angle(calibration.count_min),
+            )
+        };
+        if let Err(e) = self.set_position_blocking(azimuth, elevation) {
+            warn!("Stow move incomplete: {}", e);
+        }
+
+        // the blocking move is coarse, wait for the readback to reach the stop
+        let deadline =
+            std::time::Instant::now() + std::time::Duration::from_millis(STOW_TIMEOUT_MS);
+        loop {
+            let count = self.state.read().unwrap().elevation_count;
+            if (count - calibration.count_min).abs() <= STOW_COUNT_TOLERANCE {
+                info!("Dish stowed, elevation count: {}", count);
+                return true;
+            }
+            if std::time::Instant::now() > deadline || self.cancelled() {
+                warn!(
+                    "Dish not stowed, elevation count is {}, expected {}",
+                    count, calibration.count_min
+                );
+                return false;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+    }
+
+    /// Checks that the firmware answers and parses, and that each motor
+    /// moves on a nudge, logging a line per check. Returns whether all of
+    /// them passed.
+    pub fn self_test(&self) -> bool {
+        info!("Self-test: checking the firmware and the motors");
+        let timeout = std::time::Duration::from_millis(SELFTEST_TIMEOUT_MS);
+        let mut failed = 0;
+
+        // a version left from the startup `ver` would pass without this reply
+        self.state.write().unwrap().firmware_version = None;
+        self.send(GlobalBus::DishCommand(DishCommand::Version));
+        let answered = self.wait_for(timeout, |state| state.firmware_version.is_some());
+        if answered {
+            let version = self.state.read().unwrap().firmware_version.clone();
+            info!("Self-test: firmware PASS ({})", version.unwrap_or_default());
+        } else {
+            error!(
+                "Self-test: firmware FAIL, no reply to ver within {} ms, check the port and --baudrate",
+                SELFTEST_TIMEOUT_MS
+            );
+            failed += 1;
         }
-        info!("Set position to azimuth: {}, elevation: {}", az, el);
-        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        // a baseline from fresh position polls, not the zeroes before the first one
+        self.wait_until_settled(timeout);
+        let (el_angle, (el_min, el_max)) = {
+            let state = self.state.read().unwrap();
+            (
+                state.elevation_angle,
+                state.elevation_calibration.angle_limits(),
+            )
+        };
+        // nudge away from the closer stop, where the motor couldn't move
+        let (el_nudge, el_back) = if el_angle - el_min < el_max - el_angle {
+            (
+                DishCommand::NudgeElevationUp,
+                DishCommand::NudgeElevationDown,
+            )
+        } else {
+            (
+                DishCommand::NudgeElevationDown,
+                DishCommand::NudgeElevationUp,
+            )
+        };
+
+        for (name, nudge, back, count) in [
+            (
+                "azimuth",
+                DishCommand::NudgeAzimuthCw,
+                DishCommand::NudgeAzimuthCcw,
+                (|state: &DishState| state.azimuth_count) as fn(&DishState) -> i32,
+            ),
+            ("elevation", el_nudge, el_back, |state| {
+                state.elevation_count
+            }),
+        ] {
+            let before = count(&self.state.read().unwrap());
+            self.send(GlobalBus::DishCommand(nudge));
+            if self.wait_for(timeout, |state| count(state) != before) {
+                // let the whole nudge show up before reporting it
+                self.wait_until_settled(timeout);
+                info!(
+                    "Self-test: {} motor PASS (count {} -> {})",
+                    name,
+                    before,
+                    count(&self.state.read().unwrap())
+                );
+                self.send(GlobalBus::DishCommand(back));
+            } else {
+                error!(
+                    "Self-test: {} motor FAIL, count stayed at {} for {} ms after a nudge, is the motor connected?",
+                    name, before, SELFTEST_TIMEOUT_MS
+                );
+                failed += 1;
+            }
+        }
+
+        if failed == 0 {
+            info!("Self-test passed");
+        } else {
+            error!("Self-test FAILED: {} of 3 checks", failed);
+        }
+        failed == 0
+    }
+
+    /// polls the state until `done` holds, false if it didn't within the timeout
+    fn wait_for(&self, timeout: std::time::Duration, done: impl Fn(&DishState) -> bool) -> bool {
+        let start = std::time::Instant::now();
+        while start.elapsed() < timeout {
+            if done(&self.state.read().unwrap()) {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        false
+    }
+
+    /// Moves to the position and waits until the readback is within the
+    /// tolerance, sending the move again after each timeout. Errors if the
+    /// dish didn't get there after all the retries, or the action got
+    /// cancelled while waiting.
+    pub fn set_position_blocking(&self, az: f64, el: f64) -> Result<()> {
+        let timeout = std::time::Duration::from_millis(self.motion.timeout_ms);
+
+        for attempt in 0..=self.motion.retries {
+            if attempt > 0 {
+                if self.cancelled() {
+                    return Err(eyre!("cancelled"));
+                }
+                warn!(
+                    "Timeout while setting position, retrying ({}/{})",
+                    attempt, self.motion.retries
+                );
+                if self.motion.rehome && !self.rehome(az, el) {
+                    return Err(eyre!("cancelled"));
+                }
+            }
+            self.send(GlobalBus::DishCommand(self.azimuth_command(az)));
+            self.send(GlobalBus::DishCommand(self.elevation_command(el)));
+
+            let start = std::time::Instant::now();
+            while start.elapsed() < timeout {
+                if self.at_position(az, el) {
+                    info!("Set position to azimuth: {}, elevation: {}", az, el);
+                    self.wait_until_settled(std::time::Duration::from_millis(SETTLE_TIMEOUT_MS));
+                    return Ok(());
+                }
+                if !self.sleep_cancellable(100) {
+                    return Err(eyre!("cancelled"));
+                }
+            }
+        }
+
+        let state = self.snapshot();
+        Err(eyre!(
+            "could not reach azimuth: {}, elevation: {}, stopped at {:.2}/{:.2}",
+            az,
+            el,
+            state.azimuth_angle,
+            state.elevation_angle
+        ))
+    }
+
+    /// Waits until the azimuth and elevation counts stop changing, so a
+    /// reading isn't taken while the dish is still coasting: short after a
+    /// small step, longer after a big move. Returns false if it timed out or
+    /// was cancelled first.
+    pub fn wait_until_settled(&self, timeout: std::time::Duration) -> bool {
+        let counts = || {
+            let state = self.snapshot();
+            (state.azimuth_count, state.elevation_count)
+        };
+
+        let start = std::time::Instant::now();
+        let mut last = counts();
+        let mut steady = 0;
+        while steady < SETTLE_POLLS {
+            if start.elapsed() > timeout {
+                warn!(
+                    "Dish still moving after {} ms, measuring anyway",
+                    timeout.as_millis()
+                );
+                return false;
+            }
+            if !self.sleep_cancellable(SETTLE_POLL_MS) {
+                return false;
+            }
+
+            let now = counts();
+            if (now.0 - last.0).abs() <= SETTLE_COUNT_TOLERANCE
+                && (now.1 - last.1).abs() <= SETTLE_COUNT_TOLERANCE
+            {
+                steady += 1;
+            } else {
+                steady = 0;
+            }
+            last = now;
+        }
+        debug!("Settled after {} ms", start.elapsed().as_millis());
+        true
+    }
+
+    /// Nudges each axis that's short of the target once the other way, so
+    /// the next move starts with the gear loose. Returns false if the action
+    /// got cancelled while the nudges settled.
+    fn rehome(&self, az: f64, el: f64) -> bool {
+        let DishSnapshot {
+            azimuth_angle: az_now,
+            elevation_angle: el_now,
+            ..
+        } = self.snapshot();
+
+        let mut nudges = vec![];
+        if azimuth_distance(az_now, az) > self.motion.tolerance_deg {
+            // the short way round is clockwise when the target is ahead
+            let ahead = (az - az_now).rem_euclid(AZIMUTH_ANGLE_MAX) < AZIMUTH_ANGLE_MAX / 2.0;
+            nudges.push(if ahead {
+                DishCommand::NudgeAzimuthCcw
+            } else {
+                DishCommand::NudgeAzimuthCw
+            });
+        }
+        if (el_now - el).abs() > self.motion.tolerance_deg {
+            nudges.push(if el > el_now {
+                DishCommand::NudgeElevationDown
+            } else {
+                DishCommand::NudgeElevationUp
+            });
+        }
+
+        for nudge in nudges {
+            info!("Re-homing before the retry: {}", nudge);
+            self.send(GlobalBus::DishCommand(nudge));
+        }
+        self.sleep_cancellable(NUDGE_SETTLE_MS)
+    }
+
+    fn at_position(&self, az: f64, el: f64) -> bool {
+        let state = self.snapshot();
+        azimuth_distance(state.azimuth_angle, az) <= self.motion.tolerance_deg
+            && (state.elevation_angle - el).abs() <= self.motion.tolerance_deg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(start: i32, end: i32, step: i32) -> Sweep1DParams {
+        Sweep1DParams {
+            start,
+            end,
+            step,
+            dwell_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_half_power_offset() {
+        // halfway down between the readings
+        assert_eq!(
+            half_power_offset((1.0, 3600.0), (1.5, 3400.0), 3500.0),
+            1.25
+        );
+        // right at the first reading below
+        assert_eq!(half_power_offset((0.0, 4100.0), (0.5, 3600.0), 3600.0), 0.5);
+    }
+
+    #[test]
+    fn test_geostationary_look_angles() {
+        let close = |a: f64, b: f64| (a - b).abs() < 0.01;
+
+        let equator = GpsLocation {
+            latitude: 0.0,
+            longitude: 10.0,
+        };
+        let overhead = equator.geostationary_look_angles(10.0).unwrap();
+        assert!(close(overhead.elevation, 90.0));
+
+        // due south from the northern hemisphere, due north from the southern
+        let north = GpsLocation {
+            latitude: 45.0,
+            longitude: 19.2,
+        };
+        let astra = north.geostationary_look_angles(19.2).unwrap();
+        assert!(close(astra.azimuth, 180.0));
+        assert!(close(astra.elevation, 38.17), "{:?}", astra);
+
+        let south = GpsLocation {
+            latitude: -33.9,
+            longitude: 18.4,
+        };
+        assert!(close(
+            azimuth_distance(south.geostationary_look_angles(18.4).unwrap().azimuth, 0.0),
+            0.0
+        ));
+
+        // satellites to the east of the site are east of south
+        let east = north.geostationary_look_angles(40.0).unwrap();
+        assert!(east.azimuth > 90.0 && east.azimuth < 180.0);
+
+        assert!(north.geostationary_look_angles(-170.0).is_none());
+    }
+
+    fn scan(az: (f64, f64), el: (f64, f64), step: f64) -> Scan2DParams {
+        Scan2DParams {
+            bottom_left: DishPosition {
+                azimuth: az.0,
+                elevation: el.0,
+            },
+            top_right: DishPosition {
+                azimuth: az.1,
+                elevation: el.1,
+            },
+            step,
+            dwell_ms: 0,
+            name: None,
+            stop_on_power: None,
+            samples_per_point: 1,
+        }
+    }
+
+    #[test]
+    fn test_scan_points() {
+        let positions = |params: &Scan2DParams| -> Vec<(f64, f64)> {
+            params
+                .points()
+                .iter()
+                .map(|point| (point.azimuth, point.elevation))
+                .collect()
+        };
+
+        // the top and right bounds are left out, the columns snake up and down
+        assert_eq!(
+            positions(&scan((100.0, 115.0), (20.0, 30.0), 5.0)),
+            vec![
+                (100.0, 20.0),
+                (100.0, 25.0),
+                (105.0, 25.0),
+                (105.0, 20.0),
+                (110.0, 20.0),
+                (110.0, 25.0),
+            ]
+        );
+        // a bound the steps don't land on still ends the axis
+        assert_eq!(
+            scan((100.0, 116.0), (20.0, 31.0), 5.0).points().len(),
+            4 * 3
+        );
+        // an empty range gives no points rather than a single one
+        assert!(scan((100.0, 100.0), (20.0, 30.0), 5.0).points().is_empty());
+
+        // fractional steps stay on the grid over long axes instead of drifting
+        let fine = scan((0.0, 100.0), (10.0, 10.5), 0.1).points();
+        assert_eq!(fine.len(), 1000 * 5);
+        let last = fine.last().unwrap();
+        assert!((last.azimuth - 99.9).abs() < 1e-9, "{:?}", last);
+        assert!((last.elevation - 10.0).abs() < 1e-9, "{:?}", last);
+        for point in &fine {
+            let index = point.azimuth / 0.1;
+            assert!((index - index.round()).abs() < 1e-6, "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn test_sweep_points() {
+        assert_eq!(sweep(10, 20, 5).points(), vec![10, 15, 20]);
+        assert_eq!(sweep(10, 21, 5).points(), vec![10, 15, 20]);
+        assert_eq!(sweep(20, 10, -5).points(), vec![20, 15, 10]);
+        assert_eq!(sweep(10, 10, -5).points(), vec![10]);
+
+        assert!(sweep(10, 20, 0).validate().is_err());
+        assert!(sweep(20, 10, 5).validate().is_err());
+        assert!(sweep(10, 20, -5).validate().is_err());
+        assert!(sweep(20, 10, -5).validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_position() {
+        assert_eq!(
+            "160.2, 31.5".parse::<DishPosition>(),
+            Ok(DishPosition {
+                azimuth: 160.2,
+                elevation: 31.5
+            })
+        );
+        assert!("160.2".parse::<DishPosition>().is_err());
+        assert!("160.2,up".parse::<DishPosition>().is_err());
+    }
+
+    #[test]
+    fn test_validate_position() {
+        let state = DishState {
+            pointing_offset: crate::dish_controller::PointingOffset {
+                azimuth: 0.0,
+                elevation: -2.0,
+            },
+            ..Default::default()
+        };
+        let position = |elevation| DishPosition {
+            azimuth: 180.0,
+            elevation,
+        };
+        // the default calibration reaches 0..70 on the motors
+        assert!(position(-1.0).validate(&state).is_ok());
+        assert!(position(68.0).validate(&state).is_ok());
+        assert!(position(69.0).validate(&state).is_err());
+        assert!(position(-2.5).validate(&state).is_err());
+
+        let limited = DishState {
+            soft_limits: crate::dish_controller::SoftLimits {
+                azimuth: (90.0, 270.0),
+                elevation: (5.0, 60.0),
+            },
+            ..state
+        };
+        assert!(position(3.0).validate(&limited).is_ok());
+        assert!(position(2.9).validate(&limited).is_err());
+        assert!(position(58.1).validate(&limited).is_err());
+        let azimuth = |azimuth| DishPosition {
+            azimuth,
+            elevation: 30.0,
+        };
+        assert!(azimuth(270.0).validate(&limited).is_ok());
+        assert!(azimuth(80.0).validate(&limited).is_err());
     }
 }
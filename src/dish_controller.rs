@@ -1,91 +1,814 @@
-use log::{error, info};
-use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
+use log::{debug, error, info, warn};
+use serialport::{DataBits, FlowControl, Parity, SerialPort, SerialPortType, StopBits};
 use std::{
-    error::Error,
-    io::{BufRead, BufReader},
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
-    dish_driver::{DishCommand, DishResponse},
+    dish_driver::{DishCommand, DishResponse, NUDGE_DEG},
+    error::DishError,
     GlobalBus,
 };
 
-#[derive(Debug)]
+pub const AZIMUTH_ANGLE_MAX: f64 = 360.0;
+
+/// Shortest angular distance between two azimuths, so 359.9 and 0.1 are
+/// 0.2 degrees apart rather than 359.8.
+pub fn azimuth_distance(a: f64, b: f64) -> f64 {
+    let delta = (a - b).rem_euclid(AZIMUTH_ANGLE_MAX);
+    delta.min(AZIMUTH_ANGLE_MAX - delta)
+}
+
+/// Maps the elevation sensor readback to degrees, linearly between the two
+/// mechanical stops. The counts differ slightly between units.
+#[derive(Clone, Copy, Debug)]
+pub struct ElevationCalibration {
+    /// readback at the lower stop (0°)
+    pub count_min: i32,
+    /// readback at the upper stop (`angle_max`)
+    pub count_max: i32,
+    pub angle_max: f64,
+}
+
+impl Default for ElevationCalibration {
+    fn default() -> Self {
+        ElevationCalibration {
+            count_min: 334,
+            count_max: 1487,
+            angle_max: 70.0,
+        }
+    }
+}
+
+impl ElevationCalibration {
+    pub fn angle_to_count(&self, angle: f64) -> i32 {
+        let count = self.count_min as f64
+            + angle * (self.count_max - self.count_min) as f64 / self.angle_max;
+        // rounded, so a count converted to an angle and back is the same count
+        count.round() as i32
+    }
+
+    pub fn count_to_angle(&self, count: i32) -> f64 {
+        self.angle_max * (count - self.count_min) as f64 / (self.count_max - self.count_min) as f64
+    }
+
+    /// Checks that counts and angles map one to one, with the upper stop
+    /// above the lower one.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.count_max <= self.count_min {
+            return Err(format!(
+                "the upper elevation count {} must be above the lower one {}",
+                self.count_max, self.count_min
+            ));
+        }
+        if !(self.angle_max.is_finite() && self.angle_max > 0.0) {
+            return Err(format!(
+                "the elevation range {} must be positive",
+                self.angle_max
+            ));
+        }
+        Ok(())
+    }
+
+    /// elevation range the motor can physically reach, in degrees
+    pub fn angle_limits(&self) -> (f64, f64) {
+        (
+            self.count_to_angle(self.count_min),
+            self.count_to_angle(self.count_max),
+        )
+    }
+}
+
+/// Maps the azimuth step count to degrees. The firmware prints its own
+/// angle next to the count, this is only used instead when given explicitly,
+/// for units whose count at a given heading is off. The default is the
+/// firmware's own scale.
+#[derive(Clone, Copy, Debug)]
+pub struct AzimuthCalibration {
+    /// count at 0°
+    pub count_offset: i32,
+    pub counts_per_degree: f64,
+}
+
+impl Default for AzimuthCalibration {
+    fn default() -> Self {
+        AzimuthCalibration {
+            count_offset: 0,
+            counts_per_degree: 20.126,
+        }
+    }
+}
+
+impl AzimuthCalibration {
+    pub fn angle_to_count(&self, angle: f64) -> i32 {
+        self.count_offset + (angle * self.counts_per_degree).round() as i32
+    }
+
+    pub fn count_to_angle(&self, count: i32) -> f64 {
+        self.count_to_turn_angle(count)
+            .rem_euclid(AZIMUTH_ANGLE_MAX)
+    }
+
+    /// the angle of a count without wrapping it into 0..360, for mounts that
+    /// turn further
+    fn count_to_turn_angle(&self, count: i32) -> f64 {
+        (count - self.count_offset) as f64 / self.counts_per_degree
+    }
+}
+
+/// Fixed error of the dish pointing, added to the angles read back from the
+/// motors so they're real sky coordinates, and taken off again when
+/// commanding. Found by peaking on a known source, see `--az-offset`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PointingOffset {
+    /// degrees
+    pub azimuth: f64,
+    /// degrees
+    pub elevation: f64,
+}
+
+/// Range the motors may be commanded to, in the dish's own angles before any
+/// `PointingOffset`. Moves outside it are clamped right before they're sent,
+/// as a last line of defense against an action driving the elevation into
+/// its stop.
+#[derive(Clone, Copy, Debug)]
+pub struct SoftLimits {
+    /// degrees
+    pub azimuth: (f64, f64),
+    /// degrees
+    pub elevation: (f64, f64),
+}
+
+impl Default for SoftLimits {
+    fn default() -> Self {
+        SoftLimits {
+            azimuth: (0.0, AZIMUTH_ANGLE_MAX),
+            elevation: ElevationCalibration::default().angle_limits(),
+        }
+    }
+}
+
+impl SoftLimits {
+    /// How many of `count` nudges fit before the limit in their direction,
+    /// taking each as `NUDGE_DEG`. Nudges are open-loop, so holding an arrow
+    /// key with a multiplier would otherwise walk the dish into its stop.
+    pub fn nudges_allowed(
+        &self,
+        command: &DishCommand,
+        azimuth: f64,
+        elevation: f64,
+        count: u32,
+    ) -> u32 {
+        let (az_min, az_max) = self.azimuth;
+        let (el_min, el_max) = self.elevation;
+        let room = match command {
+            DishCommand::NudgeAzimuthCw => az_max - azimuth,
+            DishCommand::NudgeAzimuthCcw => azimuth - az_min,
+            DishCommand::NudgeElevationUp => el_max - elevation,
+            DishCommand::NudgeElevationDown => elevation - el_min,
+            _ => return count,
+        };
+        // a little slack so a nudge landing right on the limit isn't lost to rounding
+        let fitting = (room / NUDGE_DEG + 1e-6).floor().max(0.0);
+        (fitting as u32).min(count)
+    }
+
+    /// The command with its target clamped within the limits, logging when
+    /// that changed it. Only absolute moves are checked.
+    pub fn clamp(&self, command: DishCommand, calibration: &ElevationCalibration) -> DishCommand {
+        let (az_min, az_max) = self.azimuth;
+        let (el_min, el_max) = self.elevation;
+        let clamped = match command {
+            DishCommand::SetAzimuthAngle(angle) => {
+                DishCommand::SetAzimuthAngle(angle.clamp(az_min, az_max))
+            }
+            DishCommand::SetAzimuthHeading(heading) => {
+                DishCommand::SetAzimuthHeading(heading.clamp(az_min, az_max))
+            }
+            DishCommand::SetElevationAngle(angle) => {
+                DishCommand::SetElevationAngle(angle.clamp(el_min, el_max))
+            }
+            DishCommand::SetElevationCount(count) => DishCommand::SetElevationCount(count.clamp(
+                calibration.angle_to_count(el_min),
+                calibration.angle_to_count(el_max),
+            )),
+            DishCommand::SetElevationMaintain(count) => {
+                DishCommand::SetElevationMaintain(count.clamp(
+                    calibration.angle_to_count(el_min),
+                    calibration.angle_to_count(el_max),
+                ))
+            }
+            ref other => other.clone(),
+        };
+
+        if clamped != command {
+            warn!(
+                "{:?} is outside the soft limits, clamped to {:?}",
+                command, clamped
+            );
+        }
+        clamped
+    }
+}
+
+/// Exponential moving average of the rf readings, the raw ones jitter by tens
+/// of counts which makes peaking by eye hard.
+#[derive(Clone, Copy, Debug)]
+pub struct RfSmoothing {
+    /// weight of the newest reading, 1.0 disables the smoothing
+    pub alpha: f64,
+    value: Option<f64>,
+}
+
+impl Default for RfSmoothing {
+    fn default() -> Self {
+        RfSmoothing::new(0.3)
+    }
+}
+
+impl RfSmoothing {
+    pub fn new(alpha: f64) -> Self {
+        RfSmoothing { alpha, value: None }
+    }
+
+    pub fn add(&mut self, reading: f64) -> f64 {
+        let value = match self.value {
+            Some(value) => self.alpha * reading + (1.0 - self.alpha) * value,
+            None => reading,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    /// None until the first reading
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// The rf readings of one scan point, averaged into a single sample once
+/// all of them came in, see `Scan2DParams::samples_per_point`
+#[derive(Clone, Debug, Default)]
+pub struct PointReadings {
+    pub wanted: u32,
+    pub readings: Vec<f64>,
+}
+
+impl PointReadings {
+    pub fn new(wanted: u32) -> Self {
+        PointReadings {
+            wanted,
+            readings: Vec::with_capacity(wanted as usize),
+        }
+    }
+
+    /// Adds a reading, returning the mean and standard deviation of the
+    /// point when it was the last one wanted.
+    pub fn add(&mut self, reading: f64) -> Option<(f64, f64)> {
+        self.readings.push(reading);
+        (self.readings.len() == self.wanted as usize).then(|| self.mean_stddev())
+    }
+
+    pub fn mean_stddev(&self) -> (f64, f64) {
+        let count = self.readings.len() as f64;
+        let mean = self.readings.iter().sum::<f64>() / count;
+        let variance = self
+            .readings
+            .iter()
+            .map(|reading| (reading - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        (mean, variance.sqrt())
+    }
+}
+
+/// readings the noise floor is estimated from
+const NOISE_FLOOR_WINDOW: usize = 200;
+/// readings needed before there's an estimate at all
+const NOISE_FLOOR_MIN_READINGS: usize = 10;
+
+/// Rolling estimate of the noise floor: the mean of the lowest tenth of the
+/// recent readings, which holds up while the dish is on a signal part of
+/// the time.
+#[derive(Clone, Debug, Default)]
+pub struct NoiseFloor {
+    readings: VecDeque<f64>,
+    value: Option<f64>,
+}
+
+impl NoiseFloor {
+    pub fn add(&mut self, reading: f64) {
+        if self.readings.len() >= NOISE_FLOOR_WINDOW {
+            self.readings.pop_front();
+        }
+        self.readings.push_back(reading);
+
+        if self.readings.len() < NOISE_FLOOR_MIN_READINGS {
+            return;
+        }
+        let mut sorted: Vec<f64> = self.readings.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let decile = &sorted[..sorted.len().div_ceil(10)];
+        self.value = Some(decile.iter().sum::<f64>() / decile.len() as f64);
+    }
+
+    /// None until enough readings came in
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+
+    /// how far above the floor a reading is, in dB, comparable between
+    /// sessions with a different LNB gain
+    pub fn snr_db(&self, reading: f64) -> Option<f64> {
+        let floor = self.value.filter(|floor| *floor > 0.0)?;
+        Some(10.0 * (reading / floor).log10())
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct DishState {
     pub azimuth_count: i32,
     pub azimuth_angle: f64,
     pub elevation_count: i32,
     pub elevation_angle: f64,
     pub signal_strength: f64,
+    /// number of rf readings received since startup
+    pub rf_sample_count: u64,
+    pub elevation_calibration: ElevationCalibration,
+    /// None trusts the angle printed by the firmware
+    pub azimuth_calibration: Option<AzimuthCalibration>,
+    /// applied to `azimuth_angle` and `elevation_angle`, which are in sky
+    /// coordinates while counts and commands are the dish's own
+    pub pointing_offset: PointingOffset,
+    /// the dish's own azimuth, followed across 360 into the azimuth range of
+    /// `soft_limits`: the firmware reports headings modulo 360 even on a
+    /// mount that turns further, e.g. 0..450. None before the first readback.
+    pub azimuth_unwrapped: Option<f64>,
+    /// name of the plan target being scanned, if any
+    pub target: Option<String>,
+    /// smoothed `signal_strength`, for display
+    pub rf_smoothing: RfSmoothing,
+    pub noise_floor: NoiseFloor,
+    /// readings above this mean the LNB is overdriven, None disables the check
+    pub saturation_limit: Option<f64>,
+    /// the last reading was above `saturation_limit`
+    pub saturated: bool,
+    /// set while a scan point averages several readings, which then make a
+    /// single sample instead of one each
+    pub point_readings: Option<PointReadings>,
+    /// reply to the `ver` sent at startup
+    pub firmware_version: Option<String>,
+    /// the firmware's own scan is running, see `DishCommand::ScanStart`
+    pub firmware_scan: bool,
+    /// azimuth, elevation kept by a running `DishAction::Hold`
+    pub hold: Option<(f64, f64)>,
+    pub soft_limits: SoftLimits,
+    /// when the dish last answered anything, for the watchdog
+    pub last_response: Option<Instant>,
+    /// nothing came from the dish for longer than the watchdog allows, the
+    /// position and signal shown are stale
+    pub no_data: bool,
+}
+
+/// A copy of the numbers in `DishState` that change with every reply, so a
+/// loop waiting for the dish or a draw can take it and let go of the lock
+/// right away instead of holding it while comparing or formatting.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DishSnapshot {
+    pub azimuth_count: i32,
+    pub azimuth_angle: f64,
+    pub elevation_count: i32,
+    pub elevation_angle: f64,
+    pub signal_strength: f64,
+    pub rf_sample_count: u64,
+    /// `rf_smoothing` of the state
+    pub smoothed_signal: Option<f64>,
+    /// `noise_floor` of the state, and the signal strength above it
+    pub noise_floor: Option<f64>,
+    pub snr_db: Option<f64>,
+    pub saturated: bool,
+    pub firmware_scan: bool,
+    pub hold: Option<(f64, f64)>,
+    pub no_data: bool,
 }
 
 impl DishState {
+    pub fn snapshot(&self) -> DishSnapshot {
+        DishSnapshot {
+            azimuth_count: self.azimuth_count,
+            azimuth_angle: self.azimuth_angle,
+            elevation_count: self.elevation_count,
+            elevation_angle: self.elevation_angle,
+            signal_strength: self.signal_strength,
+            rf_sample_count: self.rf_sample_count,
+            smoothed_signal: self.rf_smoothing.value(),
+            noise_floor: self.noise_floor.value(),
+            snr_db: self.noise_floor.snr_db(self.signal_strength),
+            saturated: self.saturated,
+            firmware_scan: self.firmware_scan,
+            hold: self.hold,
+            no_data: self.no_data,
+        }
+    }
+
     pub fn update_from_response(&mut self, response: &DishResponse) {
+        self.last_response = Some(Instant::now());
         match response {
             DishResponse::Azimuth(az, az_angle) => {
                 self.azimuth_count = *az;
-                self.azimuth_angle = *az_angle;
+                let angle = match self.azimuth_calibration {
+                    Some(calibration) => calibration.count_to_angle(*az),
+                    None => *az_angle,
+                };
+                self.azimuth_unwrapped = Some(self.unwrap_azimuth(angle));
+                self.azimuth_angle =
+                    (angle + self.pointing_offset.azimuth).rem_euclid(AZIMUTH_ANGLE_MAX);
             }
             DishResponse::Elevation(el) => {
                 self.elevation_count = *el;
-                self.elevation_angle = Self::elevation_count_to_angle(*el);
+                self.elevation_angle = self.elevation_count_to_angle(*el);
             }
             DishResponse::RfPower(rf) => {
                 self.signal_strength = *rf;
+                self.saturated = self.is_saturating(*rf);
+                // the reading gets retried, it's no sample of the signal
+                if !self.saturated {
+                    self.rf_smoothing.add(*rf);
+                }
+                self.noise_floor.add(*rf);
+                self.rf_sample_count += 1;
             }
-            DishResponse::Ver(_) => {}
+            DishResponse::Ver(version) => self.firmware_version = Some(version.clone()),
+            DishResponse::ScanState(running) => self.firmware_scan = *running,
+            DishResponse::NvValue(..) | DishResponse::Error(_) => {}
         }
     }
-    #[allow(dead_code)]
-    pub fn elevation_angle_to_count(angle: f64) -> i32 {
-        let count = 334.0 + angle * (1487.0 - 334.0) / 70.0;
-        count as i32
+
+    /// Commands that halt the motors where they are. The firmware has no
+    /// stop verb (see its help in `dish_driver`), so both axes are sent to
+    /// their current readback instead, which replaces the move in progress.
+    /// The elevation goes by count with a plain `elev`, so it isn't left
+    /// maintaining that count against later nudges and moves. A running
+    /// firmware scan is stopped first, it would move the dish on otherwise.
+    pub fn stop_commands(&self) -> Vec<DishCommand> {
+        let mut commands = vec![];
+        if self.firmware_scan {
+            commands.push(DishCommand::ScanStop);
+        }
+        commands.push(DishCommand::SetAzimuthAngle(
+            self.firmware_azimuth(self.azimuth_angle),
+        ));
+        commands.push(DishCommand::SetElevationCount(self.elevation_count));
+        commands
+    }
+
+    pub fn is_saturating(&self, power: f64) -> bool {
+        self.saturation_limit.is_some_and(|limit| power > limit)
+    }
+
+    /// the azimuth to command for a sky azimuth, undoing the pointing offset,
+    /// on the turn of the azimuth range that's the shortest way from where
+    /// the dish is
+    pub fn dish_azimuth(&self, angle: f64) -> f64 {
+        self.unwrap_azimuth(angle - self.pointing_offset.azimuth)
+    }
+
+    /// The angle to send the firmware for a sky azimuth. The firmware moves
+    /// by its own scale, so with an `azimuth_calibration` it gets the angle
+    /// it has for the count the calibration puts at `angle`.
+    pub fn firmware_azimuth(&self, angle: f64) -> f64 {
+        if self.azimuth_calibration.is_none() {
+            return self.dish_azimuth(angle);
+        }
+        AzimuthCalibration::default().count_to_turn_angle(self.azimuth_angle_to_count(angle))
+    }
+
+    /// Of the headings that are `angle` modulo 360 within the azimuth soft
+    /// limits, the one closest to `azimuth_unwrapped`. Before the first
+    /// readback the dish is taken to be on the lowest turn. An angle the
+    /// limits don't reach is left in 0..360 for the soft limits to clamp.
+    fn unwrap_azimuth(&self, angle: f64) -> f64 {
+        let (min, max) = self.soft_limits.azimuth;
+        let near = self.azimuth_unwrapped.unwrap_or(min);
+
+        let mut turn = min + (angle - min).rem_euclid(AZIMUTH_ANGLE_MAX);
+        let mut closest = turn;
+        while turn <= max {
+            if (turn - near).abs() < (closest - near).abs() {
+                closest = turn;
+            }
+            turn += AZIMUTH_ANGLE_MAX;
+        }
+        if closest > max {
+            angle.rem_euclid(AZIMUTH_ANGLE_MAX)
+        } else {
+            closest
+        }
+    }
+
+    /// the elevation to command for a sky elevation, undoing the pointing
+    /// offset
+    pub fn dish_elevation(&self, angle: f64) -> f64 {
+        angle - self.pointing_offset.elevation
+    }
+
+    pub fn azimuth_angle_to_count(&self, angle: f64) -> i32 {
+        self.azimuth_calibration
+            .unwrap_or_default()
+            .angle_to_count(self.dish_azimuth(angle))
+    }
+
+    pub fn elevation_angle_to_count(&self, angle: f64) -> i32 {
+        self.elevation_calibration
+            .angle_to_count(self.dish_elevation(angle))
+    }
+
+    pub fn elevation_count_to_angle(&self, count: i32) -> f64 {
+        self.elevation_calibration.count_to_angle(count) + self.pointing_offset.elevation
+    }
+}
+
+/// DishController: an abstraction for controlling the dish.
+///
+/// Responses are delivered asynchronously on the main channel as
+/// `GlobalBus::DishResponse`.
+pub trait DishController: Send {
+    fn send_command(&mut self, command: DishCommand) -> Result<(), DishError>;
+
+    /// name shown in the UI, e.g. the serial port
+    fn port_name(&self) -> &str;
+}
+
+/// Stands in for a serial dish whose port couldn't be reopened, until the
+/// next attempt.
+pub struct DisconnectedController {
+    pub port_name: String,
+}
+
+impl DishController for DisconnectedController {
+    fn send_command(&mut self, command: DishCommand) -> Result<(), DishError> {
+        Err(DishError::Disconnected(self.port_name.clone(), command))
+    }
+
+    fn port_name(&self) -> &str {
+        &self.port_name
     }
+}
+
+/// What `DishSerialController` talks to the dish over: the serial port, or a
+/// fake one in tests. The replies are read from a clone of it on their own
+/// thread while commands are written.
+pub trait DishPort: Read + Write + Send {
+    fn try_clone(&self) -> io::Result<Box<dyn DishPort>>;
+}
 
-    pub fn elevation_count_to_angle(count: i32) -> f64 {
-        70.0 * (count as f64 - 334.0) / (1487.0 - 334.0)
+impl DishPort for Box<dyn SerialPort> {
+    fn try_clone(&self) -> io::Result<Box<dyn DishPort>> {
+        let port = SerialPort::try_clone(self.as_ref()).map_err(io::Error::from)?;
+        Ok(Box::new(port))
     }
 }
 
-/// DishController: an abstraction for controlling the dish over serial.
+/// Controls the dish over serial.
 pub struct DishSerialController {
-    serial_port: Box<dyn SerialPort>,
+    serial_port: Box<dyn DishPort>,
     pub serial_port_name: String,
     pub _baudrate: u32,
+    /// index of the dish, tagging its polls and responses on the bus
+    dish: usize,
     pub mainchan_sender: crossbeam::channel::Sender<GlobalBus>,
+    /// unparsed lines are echoed to the log until this deadline, so the user
+    /// sees the reply to a raw command
+    echo_until: Arc<Mutex<Option<Instant>>>,
+    /// position polls still waiting for their response
+    pending_polls: Arc<Mutex<PendingPolls>>,
+    /// capture of everything received, see `SerialRecorder`
+    record: Option<File>,
+    /// set when the controller is dropped, e.g. to reconnect, so its threads
+    /// stop polling and reading
+    stop: Arc<AtomicBool>,
+}
+
+/// When the outstanding `azacc`/`elacc` polls were sent, so a new one only goes
+/// out once the previous got its answer (or timed out) instead of piling up
+/// on a slow link.
+#[derive(Debug, Default)]
+struct PendingPolls {
+    azimuth: Option<Instant>,
+    elevation: Option<Instant>,
+}
+
+/// a poll without a response for this long is considered lost
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// true if a poll sent at `sent` (None if answered) can be followed by a new one
+fn poll_due(sent: Option<Instant>, now: Instant) -> bool {
+    sent.is_none_or(|sent| now.duration_since(sent) >= POLL_TIMEOUT)
+}
+
+/// The serial ports on this machine, to point the user at the right one
+/// when opening a port failed.
+pub fn available_ports_hint() -> String {
+    match serialport::available_ports() {
+        Ok(ports) if ports.is_empty() => "no serial ports found".to_string(),
+        Ok(ports) => format!(
+            "available ports: {}",
+            ports
+                .iter()
+                .map(|port| port.port_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Err(e) => format!("could not list the serial ports: {}", e),
+    }
+}
+
+/// Finds the dish when no port was given: the one USB CDC-ACM device
+/// (ttyACM* on Linux, usbmodem* on macOS, a USB COM port on Windows).
+/// Errors if there's none, or more than one to choose from.
+pub fn detect_port() -> Result<String, String> {
+    let ports = serialport::available_ports()
+        .map_err(|e| format!("could not list the serial ports: {}", e))?;
+    let candidates: Vec<String> = ports
+        .into_iter()
+        .filter(|port| {
+            port.port_name.contains("ttyACM")
+                || port.port_name.contains("usbmodem")
+                || (cfg!(windows) && matches!(port.port_type, SerialPortType::UsbPort(_)))
+        })
+        .map(|port| port.port_name)
+        .collect();
+
+    match &candidates[..] {
+        [port] => Ok(port.clone()),
+        [] => Err(format!(
+            "no USB serial device found, pass --port ({})",
+            available_ports_hint()
+        )),
+        _ => Err(format!(
+            "several possible dish ports: {}, pick one with --port",
+            candidates.join(", ")
+        )),
+    }
 }
 
+/// Line settings of the serial port. The dish's own USB port ignores them
+/// besides the baudrate, RS-232 adapters in between may not.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SerialSettings {
+    pub baudrate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub flow_control: FlowControl,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        SerialSettings {
+            baudrate: 9600,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        }
+    }
+}
+
+/// the usual shorthand, e.g. `9600 8N1`
+impl std::fmt::Display for SerialSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        write!(
+            f,
+            "{} {}{}{}",
+            self.baudrate,
+            u8::from(self.data_bits),
+            parity,
+            u8::from(self.stop_bits)
+        )?;
+        match self.flow_control {
+            FlowControl::None => Ok(()),
+            FlowControl::Software => write!(f, " XON/XOFF"),
+            FlowControl::Hardware => write!(f, " RTS/CTS"),
+        }
+    }
+}
+
+pub fn parse_data_bits(s: &str) -> Result<DataBits, String> {
+    match s {
+        "5" => Ok(DataBits::Five),
+        "6" => Ok(DataBits::Six),
+        "7" => Ok(DataBits::Seven),
+        "8" => Ok(DataBits::Eight),
+        _ => Err(format!("expected 5, 6, 7 or 8, got {:?}", s)),
+    }
+}
+
+pub fn parse_parity(s: &str) -> Result<Parity, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "none" | "n" => Ok(Parity::None),
+        "odd" | "o" => Ok(Parity::Odd),
+        "even" | "e" => Ok(Parity::Even),
+        _ => Err(format!("expected none, odd or even, got {:?}", s)),
+    }
+}
+
+pub fn parse_stop_bits(s: &str) -> Result<StopBits, String> {
+    match s {
+        "1" => Ok(StopBits::One),
+        "2" => Ok(StopBits::Two),
+        _ => Err(format!("expected 1 or 2, got {:?}", s)),
+    }
+}
+
+pub fn parse_flow_control(s: &str) -> Result<FlowControl, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "none" => Ok(FlowControl::None),
+        "software" | "xonxoff" => Ok(FlowControl::Software),
+        "hardware" | "rtscts" => Ok(FlowControl::Hardware),
+        _ => Err(format!("expected none, software or hardware, got {:?}", s)),
+    }
+}
+
+/// how long the reply to a raw command is echoed for
+const RAW_ECHO_DURATION: Duration = Duration::from_secs(2);
+/// a command is given up on after failing this many times, as long as none
+/// of it went out
+const SEND_ATTEMPTS: u32 = 3;
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(50);
+
 impl DishSerialController {
     /// Create and connect the DishController, opening the specified serial port.
     pub fn new(
         port_name: &str,
-        baudrate: u32,
+        settings: SerialSettings,
         channel: crossbeam::channel::Sender<GlobalBus>,
-    ) -> Result<DishSerialController, Box<dyn Error>> {
+        record: Option<&Path>,
+        dish: usize,
+    ) -> Result<DishSerialController, DishError> {
         // Configure the serial port options
-        let sp = serialport::new(port_name, baudrate)
-            .data_bits(DataBits::Eight)
-            .flow_control(FlowControl::None)
-            .stop_bits(StopBits::One)
-            .parity(Parity::None)
+        let sp = serialport::new(port_name, settings.baudrate)
+            .data_bits(settings.data_bits)
+            .flow_control(settings.flow_control)
+            .stop_bits(settings.stop_bits)
+            .parity(settings.parity)
             .timeout(Duration::from_secs(1))
-            .open()?;
+            .open()
+            .map_err(|source| DishError::PortOpen {
+                port: port_name.to_string(),
+                source,
+            })?;
 
-        info!(
-            "Serial port '{}' opened at baudrate {}.",
-            port_name, baudrate
-        );
+        info!("Serial port '{}' opened at {}.", port_name, settings);
+
+        let record = match record {
+            Some(path) => {
+                info!("Recording the raw serial output to {}", path.display());
+                Some(OpenOptions::new().create(true).append(true).open(path)?)
+            }
+            None => None,
+        };
+
+        DishSerialController::from_port(Box::new(sp), port_name, settings, channel, record, dish)
+    }
 
+    /// Starts controlling the dish over an already open port, see `DishPort`.
+    pub fn from_port(
+        port: Box<dyn DishPort>,
+        port_name: &str,
+        settings: SerialSettings,
+        channel: crossbeam::channel::Sender<GlobalBus>,
+        record: Option<File>,
+        dish: usize,
+    ) -> Result<DishSerialController, DishError> {
         let mut res = DishSerialController {
-            serial_port: sp,
+            serial_port: port,
             mainchan_sender: channel,
             serial_port_name: port_name.to_string(),
-            _baudrate: baudrate,
+            _baudrate: settings.baudrate,
+            dish,
+            echo_until: Arc::new(Mutex::new(None)),
+            pending_polls: Arc::new(Mutex::new(PendingPolls::default())),
+            record,
+            stop: Arc::new(AtomicBool::new(false)),
         };
 
-        res.rx_thread();
+        res.rx_thread()?;
         res.tx_thread();
 
         Ok(res)
@@ -93,60 +816,856 @@ impl DishSerialController {
 
     fn tx_thread(&self) {
         let sender_clone = self.mainchan_sender.clone();
+        let pending_polls = self.pending_polls.clone();
+        let dish = self.dish;
+        let stop = self.stop.clone();
 
         thread::spawn(move || {
-            loop {
+            while !stop.load(Ordering::Relaxed) {
                 // this thread just constantly asks for the azimuth and elevation
                 // response is handled by the rx_thread
-
-                if let Err(e) = sender_clone.send(GlobalBus::DishCommand(DishCommand::GetAzimuth)) {
-                    error!("{:?}", e);
-                }
-                if let Err(e) = sender_clone.send(GlobalBus::DishCommand(DishCommand::GetElevation))
+                let mut polls = vec![];
                 {
-                    error!("{:?}", e);
+                    let now = Instant::now();
+                    let mut pending = pending_polls.lock().unwrap();
+                    if poll_due(pending.azimuth, now) {
+                        pending.azimuth = Some(now);
+                        polls.push(DishCommand::GetAzimuth);
+                    }
+                    if poll_due(pending.elevation, now) {
+                        pending.elevation = Some(now);
+                        polls.push(DishCommand::GetElevation);
+                    }
                 }
 
-                thread::sleep(Duration::from_millis(100));
+                for poll in polls {
+                    if let Err(e) = sender_clone.send(GlobalBus::PollCommand(dish, poll)) {
+                        error!("{:?}", e);
+                    }
+                }
+
+                thread::sleep(POLL_INTERVAL);
             }
         });
     }
 
-    fn rx_thread(&mut self) {
-        let rx_port = self.serial_port.try_clone().unwrap();
+    fn rx_thread(&mut self) -> io::Result<()> {
+        let rx_port = self.serial_port.try_clone()?;
+        let rx_port: Box<dyn Read + Send> = match self.record.take() {
+            Some(file) => Box::new(SerialRecorder::new(rx_port, file)),
+            None => Box::new(rx_port),
+        };
+        let rx_port = StoppableReader {
+            inner: rx_port,
+            stop: self.stop.clone(),
+        };
 
         let sender = self.mainchan_sender.clone();
+        let echo_until = self.echo_until.clone();
+        let pending_polls = self.pending_polls.clone();
+        let dish = self.dish;
         thread::spawn(move || {
-            let mut reader = BufReader::with_capacity(1, rx_port);
-            let mut input_line = String::new();
-            loop {
-                if reader.read_line(&mut input_line).is_ok() {
-                    input_line = input_line.trim().to_string();
-
-                    if input_line.is_empty() {
-                        input_line.clear();
-                        continue;
-                    }
+            read_responses(
+                BufReader::with_capacity(1, rx_port),
+                Duration::ZERO,
+                sender,
+                dish,
+                echo_until,
+                pending_polls,
+            )
+        });
+        Ok(())
+    }
+}
 
-                    let dish_response = DishResponse::parse(&input_line);
-                    if let Some(dr) = dish_response {
-                        sender.send(GlobalBus::DishResponse(dr)).unwrap();
-                    }
+impl Drop for DishSerialController {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Reads as if the input ended once `stop` is set, which ends `read_responses`
+struct StoppableReader<R> {
+    inner: R,
+    stop: Arc<AtomicBool>,
+}
+
+impl<R: Read> Read for StoppableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.stop.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+        self.inner.read(buf)
+    }
+}
+
+/// Parses the lines coming from the dish and puts the responses on the bus,
+/// tagged with the index of the dish, until the input ends or the app shuts
+/// down. `line_delay` paces the lines when they come from a file rather than
+/// the port.
+fn read_responses(
+    mut reader: impl BufRead,
+    line_delay: Duration,
+    sender: crossbeam::channel::Sender<GlobalBus>,
+    dish: usize,
+    echo_until: Arc<Mutex<Option<Instant>>>,
+    pending_polls: Arc<Mutex<PendingPolls>>,
+) {
+    let mut input_line = String::new();
+    loop {
+        match reader.read_line(&mut input_line) {
+            Ok(0) => {
+                info!("End of the dish output");
+                return;
+            }
+            Ok(_) => {}
+            // a timeout while the dish is quiet keeps the partial line for the next
+            // read, bytes that aren't utf-8 are dropped
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::InvalidData
+                ) =>
+            {
+                continue
+            }
+            // e.g. the cable was pulled, the watchdog takes it from here
+            Err(e) => {
+                error!("Reading from the dish failed: {}", e);
+                return;
+            }
+        }
+        thread::sleep(line_delay);
+
+        input_line = input_line.trim().to_string();
+
+        if input_line.is_empty() {
+            input_line.clear();
+            continue;
+        }
+
+        if sender
+            .send(GlobalBus::RawLine(dish, input_line.clone()))
+            .is_err()
+        {
+            return;
+        }
+
+        let dish_response = DishResponse::parse(&input_line);
+        match dish_response {
+            Some(DishResponse::Azimuth(..)) => pending_polls.lock().unwrap().azimuth = None,
+            Some(DishResponse::Elevation(_)) => pending_polls.lock().unwrap().elevation = None,
+            _ => {}
+        }
+
+        let message = match dish_response {
+            Some(dr) => Some(GlobalBus::DishResponse(dish, dr)),
+            None if echo_until
+                .lock()
+                .unwrap()
+                .is_some_and(|deadline| Instant::now() < deadline) =>
+            {
+                info!("< {}", input_line);
+                None
+            }
+            None if !DishResponse::is_chatter(&input_line) => {
+                Some(GlobalBus::UnparsedLine(dish, input_line.clone()))
+            }
+            None => None,
+        };
+
+        if let Some(message) = message {
+            if sender.send(message).is_err() {
+                // the app has shut down
+                return;
+            }
+        }
+
+        input_line.clear();
+    }
+}
+
+/// Copies everything read through it to a capture file, raw, each line
+/// prefixed with the time it started arriving:
+/// `[1741745129.123] Current rfss: ...`
+struct SerialRecorder<R> {
+    inner: R,
+    /// None once writing to it failed, the port keeps being read regardless
+    file: Option<File>,
+    line_start: bool,
+}
+
+impl<R> SerialRecorder<R> {
+    fn new(inner: R, file: File) -> Self {
+        SerialRecorder {
+            inner,
+            file: Some(file),
+            line_start: true,
+        }
+    }
+
+    fn record(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        for line in bytes.split_inclusive(|&b| b == b'\n') {
+            if self.line_start {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                write!(file, "[{:.3}] ", now.as_secs_f64())?;
+            }
+            file.write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for SerialRecorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Err(e) = self.record(&buf[..read]) {
+            error!("Stopped recording the serial output: {}", e);
+            self.file = None;
+        }
+        Ok(read)
+    }
+}
+
+/// Removes the timestamps `SerialRecorder` puts in front of the lines, so
+/// its captures can be played back as they are.
+fn strip_capture_timestamps(capture: &[u8]) -> Vec<u8> {
+    capture
+        .split_inclusive(|&b| b == b'\n')
+        .flat_map(|line| {
+            let stamp = line.strip_prefix(b"[").and_then(|rest| {
+                let end = rest.iter().position(|&b| b == b']')?;
+                let digits = &rest[..end];
+                (!digits.is_empty() && digits.iter().all(|b| b.is_ascii_digit() || *b == b'.'))
+                    .then(|| &rest[end + 1..])
+            });
+            match stamp {
+                Some(rest) => rest.strip_prefix(b" ").unwrap_or(rest),
+                None => line,
+            }
+        })
+        .copied()
+        .collect()
+}
+
+/// Plays back a capture of the raw serial output through the same parsing
+/// as the live port, to check parser changes against real firmware output.
+/// Captures made with `--record-serial` are read as they are. Commands go
+/// nowhere.
+pub struct SerialLogController {
+    path: String,
+}
 
-                    input_line.clear();
+impl SerialLogController {
+    pub fn new(
+        path: &Path,
+        lines_per_second: f64,
+        channel: crossbeam::channel::Sender<GlobalBus>,
+    ) -> Result<SerialLogController, DishError> {
+        let capture = strip_capture_timestamps(&std::fs::read(path)?);
+        info!(
+            "Playing back serial log {} at {} lines/s",
+            path.display(),
+            lines_per_second
+        );
+
+        let line_delay = Duration::from_secs_f64(1.0 / lines_per_second);
+        thread::spawn(move || {
+            read_responses(
+                io::Cursor::new(capture),
+                line_delay,
+                channel,
+                0,
+                Arc::new(Mutex::new(None)),
+                Arc::new(Mutex::new(PendingPolls::default())),
+            )
+        });
+
+        Ok(SerialLogController {
+            path: path.display().to_string(),
+        })
+    }
+}
+
+impl DishController for SerialLogController {
+    fn port_name(&self) -> &str {
+        &self.path
+    }
+
+    fn send_command(&mut self, command: DishCommand) -> Result<(), DishError> {
+        debug!("Playing back a serial log, not sending {:?}", command);
+        Ok(())
+    }
+}
+
+impl DishController for DishSerialController {
+    fn port_name(&self) -> &str {
+        &self.serial_port_name
+    }
+
+    fn send_command(&mut self, command: DishCommand) -> Result<(), DishError> {
+        if let DishCommand::Raw(line) = &command {
+            info!("> {}", line);
+            *self.echo_until.lock().unwrap() = Some(Instant::now() + RAW_ECHO_DURATION);
+        }
+        // the whole line goes out in a single write, writing it char by char
+        // was slower and let the rx side interleave with a half sent command
+        let line = format!("{}\r", command.serialize());
+
+        let mut attempt = 1;
+        loop {
+            let mut sent = 0;
+            let result = write_counted(&mut self.serial_port, line.as_bytes(), &mut sent)
+                .and_then(|_| self.serial_port.flush());
+
+            match result {
+                Ok(()) => return Ok(()),
+                // nothing reached the dish, the whole line can go again
+                Err(e) if sent == 0 && attempt < SEND_ATTEMPTS => {
+                    warn!(
+                        "Sending {:?} failed (attempt {}/{}): {}",
+                        command, attempt, SEND_ATTEMPTS, e
+                    );
+                    thread::sleep(SEND_RETRY_DELAY);
+                    attempt += 1;
+                }
+                Err(e) if sent == 0 && e.kind() == io::ErrorKind::TimedOut => {
+                    return Err(DishError::Timeout(command))
+                }
+                // a retry would be appended to the part that went out, and
+                // ending that part with a CR would run it, e.g. `azangle 18`
+                // of `azangle 180`
+                Err(e) => {
+                    if sent > 0 {
+                        error!(
+                            "Only {} of {} bytes of {:?} went out, not retrying",
+                            sent,
+                            line.len(),
+                            command
+                        );
+                    }
+                    return Err(DishError::Serial(e));
                 }
             }
+        }
+    }
+}
+
+/// `write_all` that counts the bytes written, so a failed write tells how
+/// much of the line the dish got
+fn write_counted<W: Write + ?Sized>(
+    port: &mut W,
+    mut buf: &[u8],
+    sent: &mut usize,
+) -> io::Result<()> {
+    while !buf.is_empty() {
+        match port.write(buf) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => {
+                *sent += written;
+                buf = &buf[written..];
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elevation_conversion_endpoints() {
+        let state = DishState::default();
+        assert_eq!(state.elevation_angle_to_count(0.0), 334);
+        assert_eq!(state.elevation_angle_to_count(70.0), 1487);
+        assert_eq!(state.elevation_count_to_angle(334), 0.0);
+        assert_eq!(state.elevation_count_to_angle(1487), 70.0);
+        for count in 334..=1487 {
+            let angle = state.elevation_count_to_angle(count);
+            assert_eq!(state.elevation_angle_to_count(angle), count);
+        }
+    }
+
+    #[test]
+    fn test_elevation_calibration_validate() {
+        assert!(ElevationCalibration::default().validate().is_ok());
+        for (count_min, count_max, angle_max) in
+            [(334, 334, 70.0), (1487, 334, 70.0), (334, 1487, 0.0)]
+        {
+            let calibration = ElevationCalibration {
+                count_min,
+                count_max,
+                angle_max,
+            };
+            assert!(calibration.validate().is_err(), "{:?}", calibration);
+        }
+    }
+
+    #[test]
+    fn test_point_readings() {
+        let mut point = PointReadings::new(3);
+        assert_eq!(point.add(3100.0), None);
+        assert_eq!(point.add(3300.0), None);
+        let (mean, stddev) = point.add(3200.0).unwrap();
+        assert_eq!(mean, 3200.0);
+        assert!((stddev - 81.6497).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_serial_settings() {
+        let settings = SerialSettings {
+            data_bits: parse_data_bits("7").unwrap(),
+            parity: parse_parity("Even").unwrap(),
+            stop_bits: parse_stop_bits("2").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(settings.to_string(), "9600 7E2");
+        assert_eq!(SerialSettings::default().to_string(), "9600 8N1");
+        assert_eq!(parse_flow_control("rtscts"), Ok(FlowControl::Hardware));
+
+        assert!(parse_data_bits("9").is_err());
+        assert!(parse_parity("mark").is_err());
+        assert!(parse_stop_bits("1.5").is_err());
+    }
+
+    #[test]
+    fn test_azimuth_calibration() {
+        let mut state = DishState::default();
+        state.update_from_response(&DishResponse::Azimuth(3622, 180.0));
+        assert_eq!(state.azimuth_angle, 180.0);
+        // 3622.68, rounded
+        assert_eq!(state.azimuth_angle_to_count(180.0), 3623);
+        assert_eq!(state.firmware_azimuth(180.0), 180.0);
+
+        // a unit whose count is 100 ahead at the same heading
+        state.azimuth_calibration = Some(AzimuthCalibration {
+            count_offset: 100,
+            ..Default::default()
         });
+        state.update_from_response(&DishResponse::Azimuth(3722, 184.94));
+        assert!((state.azimuth_angle - 180.0).abs() < 0.1);
+        assert_eq!(state.azimuth_angle_to_count(180.0), 3723);
+        // the firmware is sent its own angle for that count
+        assert!((state.firmware_azimuth(180.0) - 3723.0 / 20.126).abs() < 1e-9);
+        let DishCommand::SetAzimuthAngle(stop) = state.stop_commands()[0] else {
+            panic!("no azangle to stop with");
+        };
+        assert!((stop - 3722.0 / 20.126).abs() < 1e-9);
+
+        state.update_from_response(&DishResponse::Azimuth(50, 0.0));
+        assert!((state.azimuth_angle - 357.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_stop_commands() {
+        let mut state = DishState::default();
+        state.update_from_response(&DishResponse::Azimuth(3622, 180.0));
+        state.update_from_response(&DishResponse::Elevation(700));
+        assert_eq!(
+            state.stop_commands(),
+            vec![
+                DishCommand::SetAzimuthAngle(180.0),
+                DishCommand::SetElevationCount(700),
+            ]
+        );
+
+        state.update_from_response(&DishResponse::ScanState(true));
+        assert_eq!(state.stop_commands()[0], DishCommand::ScanStop);
+    }
+
+    #[test]
+    fn test_azimuth_unwrap() {
+        let mut state = DishState {
+            soft_limits: SoftLimits {
+                azimuth: (0.0, 450.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let heading = |state: &mut DishState, angle: f64| {
+            state.update_from_response(&DishResponse::Azimuth(0, angle));
+            state.azimuth_unwrapped.unwrap()
+        };
+
+        // the lower turn until the dish is seen crossing north
+        assert_eq!(heading(&mut state, 30.0), 30.0);
+        assert_eq!(state.dish_azimuth(20.0), 20.0);
+        // 350 is the long way round, but -10 is outside the range
+        assert_eq!(state.dish_azimuth(350.0), 350.0);
+
+        assert_eq!(heading(&mut state, 355.0), 355.0);
+        assert_eq!(heading(&mut state, 2.0), 362.0);
+        assert_eq!(state.azimuth_angle, 2.0);
+        // the overlap takes the short way, beyond it only the lower turn is left
+        assert_eq!(state.dish_azimuth(10.0), 370.0);
+        assert_eq!(state.dish_azimuth(300.0), 300.0);
+        assert_eq!(
+            state.stop_commands()[0],
+            DishCommand::SetAzimuthAngle(362.0)
+        );
+
+        assert_eq!(heading(&mut state, 100.0), 100.0);
+        assert_eq!(state.dish_azimuth(10.0), 10.0);
+
+        // with the usual range nothing is unwrapped
+        let mut plain = DishState::default();
+        assert_eq!(heading(&mut plain, 359.0), 359.0);
+        assert_eq!(heading(&mut plain, 1.0), 1.0);
+        assert_eq!(plain.dish_azimuth(-1.0), 359.0);
+    }
+
+    #[test]
+    fn test_pointing_offset() {
+        let mut state = DishState {
+            pointing_offset: PointingOffset {
+                azimuth: 2.5,
+                elevation: -1.0,
+            },
+            ..Default::default()
+        };
+        state.update_from_response(&DishResponse::Azimuth(3622, 180.0));
+        state.update_from_response(&DishResponse::Elevation(334));
+        assert_eq!(state.azimuth_angle, 182.5);
+        assert_eq!(state.elevation_angle, -1.0);
+
+        // commanding the displayed position goes back to the same counts
+        assert_eq!(state.azimuth_angle_to_count(182.5), 3623);
+        assert_eq!(state.elevation_angle_to_count(-1.0), 334);
+        assert_eq!(state.dish_azimuth(1.0), 358.5);
+        assert_eq!(
+            state.stop_commands()[0],
+            DishCommand::SetAzimuthAngle(180.0)
+        );
+
+        state.update_from_response(&DishResponse::Azimuth(0, 359.0));
+        assert_eq!(state.azimuth_angle, 1.5);
+    }
+
+    #[test]
+    fn test_soft_limits_nudges() {
+        let limits = SoftLimits {
+            azimuth: (90.0, 270.0),
+            elevation: (5.0, 60.0),
+        };
+        let up = DishCommand::NudgeElevationUp;
+        assert_eq!(limits.nudges_allowed(&up, 180.0, 59.8, 1), 1);
+        assert_eq!(limits.nudges_allowed(&up, 180.0, 59.9, 1), 0);
+        assert_eq!(limits.nudges_allowed(&up, 180.0, 60.0, 1), 0);
+        assert_eq!(limits.nudges_allowed(&up, 180.0, 55.0, 50), 25);
+        assert_eq!(limits.nudges_allowed(&up, 180.0, 30.0, 50), 50);
+        let down = DishCommand::NudgeElevationDown;
+        assert_eq!(limits.nudges_allowed(&down, 180.0, 60.0, 1), 1);
+        assert_eq!(limits.nudges_allowed(&down, 180.0, 4.9, 1), 0);
+        let cw = DishCommand::NudgeAzimuthCw;
+        assert_eq!(limits.nudges_allowed(&cw, 270.1, 30.0, 1), 0);
+        let ccw = DishCommand::NudgeAzimuthCcw;
+        assert_eq!(limits.nudges_allowed(&ccw, 90.0, 30.0, 1), 0);
+        assert_eq!(limits.nudges_allowed(&ccw, 270.1, 30.0, 3), 3);
+    }
+
+    #[test]
+    fn test_elevation_conversion_round_trip() {
+        // one count is the best resolution the conversion can give
+        let count_resolution = 70.0 / (1487.0 - 334.0);
+        let state = DishState::default();
+
+        let mut angle = 0.0;
+        while angle <= 70.0 {
+            let round_trip = state.elevation_count_to_angle(state.elevation_angle_to_count(angle));
+            assert!(
+                (round_trip - angle).abs() <= count_resolution,
+                "{} came back as {}",
+                angle,
+                round_trip
+            );
+            angle += 0.1;
+        }
+    }
+
+    #[test]
+    fn test_azimuth_distance_wraps() {
+        assert!((azimuth_distance(359.9, 0.1) - 0.2).abs() < 1e-9);
+        assert!((azimuth_distance(0.1, 359.9) - 0.2).abs() < 1e-9);
+        assert_eq!(azimuth_distance(90.0, 180.0), 90.0);
+        assert_eq!(azimuth_distance(0.0, 180.0), 180.0);
+        assert_eq!(azimuth_distance(10.0, 10.0), 0.0);
+    }
+
+    #[test]
+    fn test_strip_capture_timestamps() {
+        let capture = b"[1741745129.123] Current rfss: \x1b[5D3200\r\n[1741745129.2] GO> \r\nno stamp\n[partial";
+        assert_eq!(
+            strip_capture_timestamps(capture),
+            b"Current rfss: \x1b[5D3200\r\nGO> \r\nno stamp\n[partial".to_vec()
+        );
     }
 
-    pub fn send_command(&mut self, command: DishCommand) -> Result<(), Box<dyn Error>> {
-        let cmd_str = command.serialize();
-        for ch in cmd_str.chars() {
-            self.serial_port.write_all(ch.to_string().as_bytes())?;
+    #[test]
+    fn test_soft_limits() {
+        let limits = SoftLimits {
+            azimuth: (10.0, 350.0),
+            elevation: (5.0, 60.0),
+        };
+        let calibration = ElevationCalibration::default();
+
+        assert_eq!(
+            limits.clamp(DishCommand::SetElevationAngle(75.0), &calibration),
+            DishCommand::SetElevationAngle(60.0)
+        );
+        assert_eq!(
+            limits.clamp(DishCommand::SetAzimuthAngle(-3.0), &calibration),
+            DishCommand::SetAzimuthAngle(10.0)
+        );
+        assert_eq!(
+            limits.clamp(DishCommand::SetAzimuthHeading(180.0), &calibration),
+            DishCommand::SetAzimuthHeading(180.0)
+        );
+        assert_eq!(
+            limits.clamp(DishCommand::SetElevationMaintain(0), &calibration),
+            DishCommand::SetElevationMaintain(calibration.angle_to_count(5.0))
+        );
+        assert_eq!(
+            limits.clamp(DishCommand::NudgeElevationUp, &calibration),
+            DishCommand::NudgeElevationUp
+        );
+    }
+
+    #[test]
+    fn test_noise_floor() {
+        let mut floor = NoiseFloor::default();
+        for _ in 0..NOISE_FLOOR_MIN_READINGS - 1 {
+            floor.add(3100.0);
         }
-        self.serial_port.write_all(b"\r")?;
-        self.serial_port.flush()?;
+        assert_eq!(floor.value(), None);
+        assert_eq!(floor.snr_db(3100.0), None);
 
-        Ok(())
+        // a signal most of the time doesn't drag the floor up
+        floor.add(3100.0);
+        for _ in 0..80 {
+            floor.add(6200.0);
+        }
+        assert_eq!(floor.value(), Some(3100.0));
+        assert!((floor.snr_db(6200.0).unwrap() - 3.0103).abs() < 1e-3);
+
+        // old readings age out of the window
+        for _ in 0..NOISE_FLOOR_WINDOW {
+            floor.add(3300.0);
+        }
+        assert_eq!(floor.value(), Some(3300.0));
+    }
+
+    #[test]
+    fn test_poll_due() {
+        let now = Instant::now();
+        assert!(poll_due(None, now));
+        assert!(!poll_due(Some(now), now + Duration::from_millis(100)));
+        assert!(poll_due(Some(now), now + POLL_TIMEOUT));
+    }
+
+    #[test]
+    fn test_rf_smoothing() {
+        let mut smoothing = RfSmoothing::new(0.5);
+        assert_eq!(smoothing.value(), None);
+        assert_eq!(smoothing.add(3100.0), 3100.0);
+        assert_eq!(smoothing.add(3200.0), 3150.0);
+        assert_eq!(smoothing.add(3150.0), 3150.0);
+
+        let mut raw = RfSmoothing::new(1.0);
+        raw.add(3100.0);
+        assert_eq!(raw.add(3200.0), 3200.0);
+    }
+
+    #[test]
+    fn test_saturation_flag() {
+        let mut state = DishState {
+            saturation_limit: Some(5000.0),
+            ..Default::default()
+        };
+        state.update_from_response(&DishResponse::RfPower(5200.0));
+        assert!(state.saturated);
+        assert_eq!(state.rf_smoothing.value(), None);
+        state.update_from_response(&DishResponse::RfPower(3100.0));
+        assert!(!state.saturated);
+        assert_eq!(state.rf_smoothing.value(), Some(3100.0));
+
+        let unchecked = DishState::default();
+        assert!(!unchecked.is_saturating(1e9));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut state = DishState::default();
+        state.update_from_response(&DishResponse::Azimuth(3622, 180.0));
+        state.update_from_response(&DishResponse::RfPower(3100.0));
+        let before = state.snapshot();
+
+        state.update_from_response(&DishResponse::RfPower(3300.0));
+        // a copy, not a view of the state
+        assert_eq!(before.signal_strength, 3100.0);
+        assert_eq!(before.rf_sample_count, 1);
+
+        let after = state.snapshot();
+        assert_eq!(after.azimuth_angle, state.azimuth_angle);
+        assert_eq!(after.signal_strength, 3300.0);
+        assert_eq!(after.smoothed_signal, state.rf_smoothing.value());
+        assert_eq!(after.rf_sample_count, 2);
+    }
+
+    /// Stands in for the dish on the serial port: answers each command line
+    /// written to it with the canned reply for that command, and keeps
+    /// everything written.
+    #[derive(Clone)]
+    struct FakePort {
+        replies: &'static [(&'static str, &'static str)],
+        output: Arc<Mutex<VecDeque<u8>>>,
+        written: Arc<Mutex<Vec<u8>>>,
+        /// bytes taken before every write fails, None takes them all
+        accepted: Arc<Mutex<Option<usize>>>,
+    }
+
+    impl FakePort {
+        fn new(replies: &'static [(&'static str, &'static str)]) -> Self {
+            FakePort {
+                replies,
+                output: Arc::new(Mutex::new(VecDeque::new())),
+                written: Arc::new(Mutex::new(vec![])),
+                accepted: Arc::new(Mutex::new(None)),
+            }
+        }
+
+        /// like a cable pulled half way through a write
+        fn fail_after(&self, bytes: usize) {
+            *self.accepted.lock().unwrap() = Some(bytes);
+        }
+
+        fn written(&self) -> String {
+            String::from_utf8_lossy(&self.written.lock().unwrap()).to_string()
+        }
+    }
+
+    impl Read for FakePort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut output = self.output.lock().unwrap();
+            if output.is_empty() {
+                drop(output);
+                // like the port's timeout while the dish is quiet
+                thread::sleep(Duration::from_millis(10));
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let read = buf.len().min(output.len());
+            for (byte, out) in output.drain(..read).zip(buf.iter_mut()) {
+                *out = byte;
+            }
+            Ok(read)
+        }
+    }
+
+    impl Write for FakePort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let buf = match &mut *self.accepted.lock().unwrap() {
+                Some(0) => return Err(io::ErrorKind::BrokenPipe.into()),
+                Some(left) => {
+                    let taken = buf.len().min(*left);
+                    *left -= taken;
+                    &buf[..taken]
+                }
+                None => buf,
+            };
+            self.written.lock().unwrap().extend_from_slice(buf);
+            // the controller writes each command as a whole line
+            for command in String::from_utf8_lossy(buf).split_terminator('\r') {
+                if let Some((_, reply)) = self.replies.iter().find(|(c, _)| *c == command) {
+                    self.output.lock().unwrap().extend(reply.bytes());
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl DishPort for FakePort {
+        fn try_clone(&self) -> io::Result<Box<dyn DishPort>> {
+            Ok(Box::new(self.clone()))
+        }
+    }
+
+    fn fake_controller(
+        port: &FakePort,
+    ) -> (
+        DishSerialController,
+        crossbeam::channel::Receiver<GlobalBus>,
+    ) {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let controller = DishSerialController::from_port(
+            Box::new(port.clone()),
+            "fake",
+            SerialSettings::default(),
+            tx,
+            None,
+            0,
+        )
+        .unwrap();
+        (controller, rx)
+    }
+
+    /// the first response of the dish put on the bus, skipping the polls and
+    /// raw lines
+    fn next_response(rx: &crossbeam::channel::Receiver<GlobalBus>) -> DishResponse {
+        loop {
+            match rx.recv_timeout(Duration::from_secs(2)).unwrap() {
+                GlobalBus::DishResponse(0, response) => return response,
+                _ => continue,
+            }
+        }
+    }
+
+    #[test]
+    fn test_serial_controller_azimuth() {
+        let port = FakePort::new(&[("azacc", "Current heading:       3224 (160.192 deg.)\r\n")]);
+        let (mut controller, rx) = fake_controller(&port);
+
+        controller.send_command(DishCommand::GetAzimuth).unwrap();
+        assert_eq!(next_response(&rx), DishResponse::Azimuth(3224, 160.192));
+        assert_eq!(port.written(), "azacc\r");
+    }
+
+    #[test]
+    fn test_serial_controller_partial_write() {
+        let port = FakePort::new(&[]);
+        let (mut controller, _rx) = fake_controller(&port);
+
+        port.fail_after(10);
+        let result = controller.send_command(DishCommand::SetAzimuthAngle(180.0));
+        assert!(matches!(result, Err(DishError::Serial(_))));
+        // neither terminated nor sent again
+        assert_eq!(port.written(), "azangle 18");
+    }
+
+    #[test]
+    fn test_serial_controller_rf_watch() {
+        // the whole window is one line, each value overwriting the last
+        let port = FakePort::new(&[(
+            "rfwatch 2",
+            "Current rfss:           \u{1b}[5D3100 \u{1b}[5D3150 \u{1b}[5D3200\r\n",
+        )]);
+        let (mut controller, rx) = fake_controller(&port);
+
+        controller.send_command(DishCommand::RfWatch(2)).unwrap();
+        assert_eq!(next_response(&rx), DishResponse::RfPower(3150.0));
+        assert!(rx
+            .try_iter()
+            .all(|event| !matches!(event, GlobalBus::DishResponse(..))));
     }
 }
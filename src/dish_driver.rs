@@ -1,24 +1,73 @@
+use std::sync::LazyLock;
+
 use regex::Regex;
-#[derive(Debug, PartialEq)]
+
+use crate::error::DishError;
+
+/// how far one `aznudge`/`elnudge` moves, approximately, per the firmware's help
+pub const NUDGE_DEG: f64 = 0.2;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum DishCommand {
+    /// Move to the given azimuth angle with `azangle`.
     SetAzimuthAngle(f64),
+    /// Rotate to the given heading with `azim`. On at least one unit it
+    /// settles at a visibly different position than `azangle` for the same
+    /// value. Why hasn't been verified, so which of the two is more accurate
+    /// has to be tried on the dish.
+    SetAzimuthHeading(f64),
     GetAzimuth,
     GetElevation,
     SetElevationAngle(f64),
+    /// Move to the given elevation count with `elev`, skipping the firmware's
+    /// approximate conversion from degrees.
+    SetElevationCount(i32),
+    /// Move to the given elevation count and keep correcting it, unlike
+    /// `SetElevationAngle` which stops driving once it got there once.
+    SetElevationMaintain(i32),
     NudgeAzimuthCcw,
     NudgeAzimuthCw,
     NudgeElevationUp,
     NudgeElevationDown,
-    RfWatch(i32),
+    /// Watch the rf signal strength for the given number of seconds. The
+    /// firmware prints a single `Current rfss:` line for the whole window,
+    /// overwriting the value in place (`\x1b[5D`) with each new reading.
+    /// The line ends with the window and its values are averaged into one
+    /// `RfPower`, so more seconds means a longer averaging window. Must be
+    /// at least 1.
+    RfWatch(u32),
     Version,
+    /// read a word of the persistent settings
+    NvRead(u16),
+    /// overwrite a word of the persistent settings, this can break the
+    /// calibration so it's only sent after a confirmation
+    NvWrite {
+        addr: u16,
+        value: u16,
+    },
+    /// Start the firmware's own scan, an azimuth sweep reading the rf at
+    /// the current elevation. Unrelated to our `Scan2d`.
+    ScanStart,
+    /// stop the firmware's scan
+    ScanStop,
+    /// reboot the firmware
+    Reset,
+    /// a line typed by the user, sent to the firmware verbatim
+    Raw(String),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum DishResponse {
     Azimuth(i32, f64),
     Elevation(i32),
     RfPower(f64),
     Ver(String),
+    /// address and value read back by `nvread`
+    NvValue(u16, u16),
+    /// the firmware rejected a command, with the whole line it replied
+    Error(String),
+    /// the firmware's scan started (true) or stopped (false)
+    ScanState(bool),
 }
 
 /*
@@ -46,18 +95,81 @@ pub enum DishResponse {
 */
 
 impl DishCommand {
+    /// Rejects arguments the firmware would misbehave on.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            DishCommand::RfWatch(0) => Err("rfwatch needs at least 1 second".to_string()),
+            _ => Ok(()),
+        }
+    }
+
     pub fn serialize(&self) -> String {
         match self {
             DishCommand::SetAzimuthAngle(angle_degrees) => format!("azangle {}", angle_degrees),
+            DishCommand::SetAzimuthHeading(heading) => format!("azim {}", heading),
             DishCommand::GetAzimuth => "azacc".to_string(),
             DishCommand::GetElevation => "elacc".to_string(),
             DishCommand::SetElevationAngle(angle) => format!("elangle {}", angle),
+            DishCommand::SetElevationCount(count) => format!("elev {}", count),
+            DishCommand::SetElevationMaintain(count) => format!("elevmt {}", count),
             DishCommand::NudgeAzimuthCcw => "aznudge ccw".to_string(),
             DishCommand::NudgeAzimuthCw => "aznudge cw".to_string(),
             DishCommand::NudgeElevationUp => "elnudge up".to_string(),
             DishCommand::NudgeElevationDown => "elnudge down".to_string(),
-            DishCommand::RfWatch(time) => format!("rfwatch {}", time),
+            DishCommand::RfWatch(seconds) => format!("rfwatch {}", seconds),
             DishCommand::Version => "ver".to_string(),
+            DishCommand::NvRead(addr) => format!("nvread {}", addr),
+            DishCommand::NvWrite { addr, value } => format!("nvwrite {} {}", addr, value),
+            DishCommand::ScanStart => "scan start".to_string(),
+            DishCommand::ScanStop => "scan stop".to_string(),
+            DishCommand::Reset => "reset".to_string(),
+            DishCommand::Raw(line) => line.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for DishCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.serialize())
+    }
+}
+
+/// Parses the wire form of the known commands back, e.g. `elangle 30` or
+/// `nvwrite 12 345`. Anything else is an error, `Raw` is never produced.
+impl std::str::FromStr for DishCommand {
+    type Err = DishError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        fn arg<T: std::str::FromStr>(verb: &str, value: &str) -> Result<T, DishError> {
+            value
+                .parse()
+                .map_err(|_| DishError::Parse(format!("invalid argument for {}: {}", verb, value)))
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts[..] {
+            ["azangle", angle] => Ok(DishCommand::SetAzimuthAngle(arg("azangle", angle)?)),
+            ["azim", heading] => Ok(DishCommand::SetAzimuthHeading(arg("azim", heading)?)),
+            ["azacc"] => Ok(DishCommand::GetAzimuth),
+            ["elacc"] => Ok(DishCommand::GetElevation),
+            ["elangle", angle] => Ok(DishCommand::SetElevationAngle(arg("elangle", angle)?)),
+            ["elev", count] => Ok(DishCommand::SetElevationCount(arg("elev", count)?)),
+            ["elevmt", count] => Ok(DishCommand::SetElevationMaintain(arg("elevmt", count)?)),
+            ["aznudge", "ccw"] => Ok(DishCommand::NudgeAzimuthCcw),
+            ["aznudge", "cw"] => Ok(DishCommand::NudgeAzimuthCw),
+            ["elnudge", "up"] => Ok(DishCommand::NudgeElevationUp),
+            ["elnudge", "down"] => Ok(DishCommand::NudgeElevationDown),
+            ["rfwatch", seconds] => Ok(DishCommand::RfWatch(arg("rfwatch", seconds)?)),
+            ["ver"] => Ok(DishCommand::Version),
+            ["nvread", addr] => Ok(DishCommand::NvRead(arg("nvread", addr)?)),
+            ["nvwrite", addr, value] => Ok(DishCommand::NvWrite {
+                addr: arg("nvwrite", addr)?,
+                value: arg("nvwrite", value)?,
+            }),
+            ["scan", "start"] => Ok(DishCommand::ScanStart),
+            ["scan", "stop"] => Ok(DishCommand::ScanStop),
+            ["reset"] => Ok(DishCommand::Reset),
+            _ => Err(DishError::Parse(format!("unknown command: {}", line))),
         }
     }
 }
@@ -69,12 +181,44 @@ impl DishCommand {
 // Received: "Stopped at Az: 3536"
 */
 
+/// how the firmware starts the replies to commands it didn't accept
+const ERROR_PREFIXES: [&str; 5] = [
+    "error",
+    "unknown command",
+    "invalid",
+    "bad ",
+    "command not found",
+];
+
+/// e.g. "NVRAM[12] = 345"
+static NV_VALUE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^NVRAM\s*\[\s*(\d+)\s*\]\s*[:=]\s*(\d+)").unwrap());
+
+/// There's no capture of the `ver` reply yet, the help only says it "Prints
+/// console version number". Anchored so error text and the echo of commands
+/// that mention a version don't count.
+static VERSION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^(console\s+)?version\b").unwrap());
+
+/// The echo of `scan start`/`scan stop` comes back whether the firmware takes
+/// the command or not, so only a report of the state counts. There's no
+/// capture of one yet, these are anchored guesses like "Scan started" or
+/// "Scan complete".
+static SCAN_RUNNING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^scan\s+(started|running|in progress)\b").unwrap());
+static SCAN_STOPPED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^scan\s+(stopped|ended|complete|completed|finished|aborted)\b").unwrap()
+});
+
 impl DishResponse {
     pub fn parse(line_from_dish: &str) -> Option<DishResponse> {
         let line = line_from_dish.trim();
         let parts: Vec<&str> = line.split_whitespace().collect();
 
         match line {
+            // the prompt and the poll echoes, several a second
+            s if s.starts_with("GO>") => None,
+            "azacc" | "elacc" => None,
             s if s.starts_with("Current heading:") => {
                 let az = parts[2].parse::<i32>().ok()?;
                 let az_angle = parts[3]
@@ -104,13 +248,38 @@ impl DishResponse {
                 });
 
                 let average = parts_clean.fold((0, 0), |(sum, count), val| (sum + val, count + 1));
+                // the window ended before its first value
+                if average.1 == 0 {
+                    return None;
+                }
                 Some(DishResponse::RfPower(average.0 as f64 / average.1 as f64))
             }
-            s if s.starts_with("GO>") => None,
-            "azacc" | "elacc" => None,
+            s if ERROR_PREFIXES
+                .iter()
+                .any(|prefix| s.to_lowercase().starts_with(prefix)) =>
+            {
+                Some(DishResponse::Error(s.to_string()))
+            }
+            s if VERSION.is_match(s) => Some(DishResponse::Ver(s.to_string())),
+            s if SCAN_RUNNING.is_match(s) => Some(DishResponse::ScanState(true)),
+            s if SCAN_STOPPED.is_match(s) => Some(DishResponse::ScanState(false)),
+            s if s.starts_with("NVRAM") => {
+                let captures = NV_VALUE.captures(s)?;
+                Some(DishResponse::NvValue(
+                    captures[1].parse().ok()?,
+                    captures[2].parse().ok()?,
+                ))
+            }
             _ => None,
         }
     }
+
+    /// Prompts and echoes of the polling commands, which come back all the
+    /// time and carry nothing worth reporting.
+    pub fn is_chatter(line_from_dish: &str) -> bool {
+        let line = line_from_dish.trim();
+        line.is_empty() || line.starts_with("GO>") || line == "azacc" || line == "elacc"
+    }
 }
 
 #[cfg(test)]
@@ -130,5 +299,97 @@ mod tests {
         let line = "Current rfss:           \u{1b}[5D3142 \u{1b}[5D3142 \u{1b}[5D3141 \u{1b}[5D3141 \u{1b}[5D3142";
         let response = DishResponse::parse(line).unwrap();
         assert_eq!(response, DishResponse::RfPower(3141.6));
+
+        let line = "NVRAM[12] = 345";
+        let response = DishResponse::parse(line).unwrap();
+        assert_eq!(response, DishResponse::NvValue(12, 345));
+
+        let line = "Console version 1.2.3";
+        let response = DishResponse::parse(line).unwrap();
+        assert_eq!(response, DishResponse::Ver(line.to_string()));
+
+        let line = "Unknown command: azangel";
+        let response = DishResponse::parse(line).unwrap();
+        assert_eq!(response, DishResponse::Error(line.to_string()));
+
+        let line = "Unknown command: version";
+        let response = DishResponse::parse(line).unwrap();
+        assert_eq!(response, DishResponse::Error(line.to_string()));
+        assert_eq!(DishResponse::parse(":nvread version"), None);
+        assert_eq!(DishResponse::parse("nvread version"), None);
+        assert_eq!(DishResponse::parse("versions"), None);
+
+        assert_eq!(
+            DishResponse::parse("Scan started"),
+            Some(DishResponse::ScanState(true))
+        );
+        assert_eq!(
+            DishResponse::parse("Scan complete"),
+            Some(DishResponse::ScanState(false))
+        );
+        // the echo of the commands
+        assert_eq!(DishResponse::parse("scan start"), None);
+        assert_eq!(DishResponse::parse("scan stop"), None);
+        assert_eq!(DishResponse::parse("scan"), None);
+    }
+
+    #[test]
+    fn test_rf_watch_validation() {
+        assert!(DishCommand::RfWatch(0).validate().is_err());
+        assert!(DishCommand::RfWatch(5).validate().is_ok());
+        assert_eq!(DishCommand::RfWatch(5).serialize(), "rfwatch 5");
+        assert_eq!(
+            DishCommand::SetElevationMaintain(900).serialize(),
+            "elevmt 900"
+        );
+    }
+
+    #[test]
+    fn test_chatter() {
+        assert!(DishResponse::is_chatter("GO> "));
+        assert!(DishResponse::is_chatter("elacc"));
+        assert!(!DishResponse::is_chatter("Stopped at Az: 3536"));
+    }
+
+    #[test]
+    fn test_command_from_str() {
+        let commands = [
+            DishCommand::SetAzimuthAngle(160.5),
+            DishCommand::SetAzimuthHeading(161.0),
+            DishCommand::GetAzimuth,
+            DishCommand::GetElevation,
+            DishCommand::SetElevationAngle(30.25),
+            DishCommand::SetElevationCount(900),
+            DishCommand::SetElevationMaintain(900),
+            DishCommand::NudgeAzimuthCcw,
+            DishCommand::NudgeAzimuthCw,
+            DishCommand::NudgeElevationUp,
+            DishCommand::NudgeElevationDown,
+            DishCommand::RfWatch(5),
+            DishCommand::Version,
+            DishCommand::NvRead(12),
+            DishCommand::NvWrite {
+                addr: 12,
+                value: 345,
+            },
+            DishCommand::ScanStart,
+            DishCommand::ScanStop,
+            DishCommand::Reset,
+        ];
+        for command in commands {
+            assert_eq!(command.to_string().parse::<DishCommand>().unwrap(), command);
+        }
+
+        assert_eq!(
+            "  nvwrite   12 345 ".parse::<DishCommand>().unwrap(),
+            DishCommand::NvWrite {
+                addr: 12,
+                value: 345
+            }
+        );
+        assert!("nvwrite 12".parse::<DishCommand>().is_err());
+        assert!("elangle up".parse::<DishCommand>().is_err());
+        assert!("aznudge left".parse::<DishCommand>().is_err());
+        assert!("stat".parse::<DishCommand>().is_err());
     }
 }
@@ -0,0 +1,32 @@
+use std::io;
+
+use thiserror::Error;
+
+use crate::dish_driver::DishCommand;
+
+/// What went wrong talking to a dish, so callers can tell a port that needs
+/// reopening from a command that will never work.
+#[derive(Debug, Error)]
+pub enum DishError {
+    /// the port couldn't be opened, e.g. a wrong path or held by another
+    /// program
+    #[error("could not open {port}: {source}")]
+    PortOpen {
+        port: String,
+        #[source]
+        source: serialport::Error,
+    },
+    /// reading or writing the port (or its recording) failed, the dish may
+    /// have been unplugged
+    #[error("serial error: {0}")]
+    Serial(#[from] io::Error),
+    /// the port didn't take the command within its timeout
+    #[error("timed out sending {0}")]
+    Timeout(DishCommand),
+    /// a command that isn't known or has bad arguments
+    #[error("{0}")]
+    Parse(String),
+    /// the port is closed until it can be reopened
+    #[error("{0} is disconnected, dropping {1}")]
+    Disconnected(String, DishCommand),
+}
@@ -0,0 +1,97 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    style::Stylize,
+    text::{Line, Span},
+};
+
+/// What a key press did to the form
+pub enum FormEvent {
+    /// still editing
+    Pending,
+    Submit,
+    Cancel,
+}
+
+/// A row of numeric fields edited in the instructions area, Tab moves
+/// between them.
+pub struct Form {
+    pub title: &'static str,
+    fields: Vec<(&'static str, String)>,
+    focus: usize,
+    /// why the last Enter was refused, shown until the next edit
+    error: Option<String>,
+}
+
+impl Form {
+    /// `fields` are the labels with their initial values
+    pub fn new(title: &'static str, fields: &[(&'static str, f64)]) -> Form {
+        Form {
+            title,
+            fields: fields
+                .iter()
+                .map(|(label, value)| (*label, value.to_string()))
+                .collect(),
+            focus: 0,
+            error: None,
+        }
+    }
+
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    pub fn handle_key(&mut self, key_event: KeyEvent) -> FormEvent {
+        let count = self.fields.len();
+        self.error = None;
+        match key_event.code {
+            KeyCode::Enter => return FormEvent::Submit,
+            KeyCode::Esc => return FormEvent::Cancel,
+            KeyCode::Tab | KeyCode::Right => self.focus = (self.focus + 1) % count,
+            KeyCode::BackTab | KeyCode::Left => self.focus = (self.focus + count - 1) % count,
+            KeyCode::Backspace => {
+                self.fields[self.focus].1.pop();
+            }
+            KeyCode::Char(c) if c.is_ascii_digit() || c == '.' || c == '-' => {
+                self.fields[self.focus].1.push(c);
+            }
+            _ => {}
+        }
+        FormEvent::Pending
+    }
+
+    /// The parsed values in field order, or the label of the first field
+    /// that isn't a number.
+    pub fn values(&self) -> Result<Vec<f64>, &'static str> {
+        self.fields
+            .iter()
+            .map(|(label, value)| value.parse().map_err(|_| *label))
+            .collect()
+    }
+
+    pub fn lines(&self) -> Vec<Line<'static>> {
+        let mut fields: Vec<Span> = vec![format!(" {} ", self.title).bold()];
+        for (i, (label, value)) in self.fields.iter().enumerate() {
+            fields.push(format!(" {}: ", label).into());
+            if i == self.focus {
+                fields.push(format!("{}_", value).yellow().bold());
+            } else {
+                fields.push(value.clone().yellow());
+            }
+        }
+        if let Some(error) = &self.error {
+            fields.push(format!("  {}", error).red().bold());
+        }
+
+        vec![
+            Line::from(fields),
+            Line::from(vec![
+                " Next field ".into(),
+                "<Tab>".blue().bold(),
+                " Run ".into(),
+                "<Enter>".blue().bold(),
+                " Abort ".into(),
+                "<Esc>".blue().bold(),
+            ]),
+        ]
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use clap::ValueEnum;
+use image::{Rgb, RgbImage};
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Widget};
+
+/// RF power samples binned into an az/el grid.
+///
+/// Cells are keyed by their index (angle / resolution, rounded), a new sample
+/// in an already filled cell replaces the old one.
+#[derive(Debug)]
+pub struct Heatmap {
+    resolution: f64,
+    cells: BTreeMap<(i64, i64), f64>,
+    /// azimuth, elevation of the peak of an earlier scan, marked with an `R`
+    /// to compare the pointing against
+    reference: Option<(f64, f64)>,
+    /// colors of the cells, also used by the sky plot and the png
+    ramp: ColorRamp,
+}
+
+impl Heatmap {
+    pub fn new(resolution: f64) -> Self {
+        Heatmap {
+            resolution,
+            cells: BTreeMap::new(),
+            reference: None,
+            ramp: ColorRamp::default(),
+        }
+    }
+
+    pub fn set_ramp(&mut self, ramp: ColorRamp) {
+        self.ramp = ramp;
+    }
+
+    pub fn ramp(&self) -> ColorRamp {
+        self.ramp
+    }
+
+    pub fn set_reference(&mut self, reference: Option<(f64, f64)>) {
+        self.reference = reference;
+    }
+
+    pub fn reference(&self) -> Option<(f64, f64)> {
+        self.reference
+    }
+
+    /// degrees per cell
+    pub fn resolution(&self) -> f64 {
+        self.resolution
+    }
+
+    /// Bins the cells again for another resolution, e.g. for a scan with a
+    /// different step. Of the cells landing in the same new one the last
+    /// is kept, like for samples.
+    pub fn set_resolution(&mut self, resolution: f64) {
+        let cells: Vec<_> = self.cells().collect();
+        self.resolution = resolution;
+        self.cells.clear();
+        for (azimuth, elevation, power) in cells {
+            self.add(azimuth, elevation, power);
+        }
+    }
+
+    fn cell_index(&self, angle: f64) -> i64 {
+        (angle / self.resolution).round() as i64
+    }
+
+    pub fn add(&mut self, azimuth: f64, elevation: f64, power: f64) {
+        let key = (self.cell_index(azimuth), self.cell_index(elevation));
+        self.cells.insert(key, power);
+    }
+
+    /// power of the cell the position falls in, if it was sampled
+    pub fn power_at(&self, azimuth: f64, elevation: f64) -> Option<f64> {
+        self.cells
+            .get(&(self.cell_index(azimuth), self.cell_index(elevation)))
+            .copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// (azimuth, elevation, power) of every filled cell
+    pub fn cells(&self) -> impl Iterator<Item = (f64, f64, f64)> + '_ {
+        self.cells.iter().map(|(&(az, el), &power)| {
+            (
+                az as f64 * self.resolution,
+                el as f64 * self.resolution,
+                power,
+            )
+        })
+    }
+
+    /// (min, max) power over all cells
+    pub fn power_range(&self) -> Option<(f64, f64)> {
+        if self.is_empty() {
+            return None;
+        }
+        let min = self.cells.values().copied().fold(f64::INFINITY, f64::min);
+        let max = self
+            .cells
+            .values()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+        Some((min, max))
+    }
+
+    /// (azimuth, elevation, power) of the strongest cell
+    pub fn peak(&self) -> Option<(f64, f64, f64)> {
+        self.cells()
+            .max_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal))
+    }
+
+    /// Rasterizes the grid to a png, with the power normalized over the collected
+    /// samples, a color scale on the right and the az/el extents as axis labels.
+    pub fn export_png(&self, path: &Path) -> image::ImageResult<()> {
+        const CELL_PX: u32 = 8;
+        /// longest side of the grid, wider ranges get smaller cells, down to
+        /// several to a pixel
+        const MAX_GRID_PX: u32 = 4096;
+        const MARGIN: u32 = 40;
+        const SCALE_WIDTH: u32 = 16;
+
+        let (Some(((az_min, az_max), (el_min, el_max))), Some((p_min, p_max))) =
+            (self.index_bounds(), self.power_range())
+        else {
+            return RgbImage::new(1, 1).save(path);
+        };
+
+        let cells = |min: i64, max: i64| max.checked_sub(min)?.checked_add(1);
+        let (Some(az_cells), Some(el_cells)) = (cells(az_min, az_max), cells(el_min, el_max))
+        else {
+            return Err(image::ImageError::Limits(
+                image::error::LimitError::from_kind(image::error::LimitErrorKind::DimensionError),
+            ));
+        };
+        let cell_px = (MAX_GRID_PX as f64 / az_cells.max(el_cells) as f64).min(CELL_PX as f64);
+        let grid_w = ((az_cells as f64 * cell_px).ceil() as u32).max(1);
+        let grid_h = ((el_cells as f64 * cell_px).ceil() as u32).max(1);
+        let cell_size = (cell_px.ceil() as u32).max(1);
+        let width = MARGIN + grid_w + MARGIN / 2 + SCALE_WIDTH + MARGIN;
+        let height = MARGIN / 2 + grid_h.max(CELL_PX * 8) + MARGIN;
+        let mut img = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+
+        let normalize = |power: f64| {
+            if p_max > p_min {
+                (power - p_min) / (p_max - p_min)
+            } else {
+                1.0
+            }
+        };
+
+        let grid_x = MARGIN;
+        let grid_y = MARGIN / 2;
+        fill_rect(&mut img, grid_x, grid_y, grid_w, grid_h, [220, 220, 220]);
+        for (&(az, el), &power) in &self.cells {
+            let x = grid_x + ((az - az_min) as f64 * cell_px) as u32;
+            // elevation grows upwards
+            let y = grid_y + ((el_max - el) as f64 * cell_px) as u32;
+            fill_rect(
+                &mut img,
+                x,
+                y,
+                cell_size,
+                cell_size,
+                self.ramp.rgb(normalize(power)),
+            );
+        }
+
+        // color scale, strongest at the top
+        let scale_x = grid_x + grid_w + MARGIN / 2;
+        let scale_h = grid_h.max(CELL_PX * 8);
+        for y in 0..scale_h {
+            let t = 1.0 - y as f64 / (scale_h - 1).max(1) as f64;
+            fill_rect(
+                &mut img,
+                scale_x,
+                grid_y + y,
+                SCALE_WIDTH,
+                1,
+                self.ramp.rgb(t),
+            );
+        }
+        let label_x = scale_x + SCALE_WIDTH + 2;
+        draw_text(&mut img, label_x, grid_y, &format!("{:.0}", p_max));
+        draw_text(
+            &mut img,
+            label_x,
+            grid_y + scale_h - GLYPH_HEIGHT,
+            &format!("{:.0}", p_min),
+        );
+
+        // axis labels: azimuth extent below the grid, elevation extent on the left
+        let az_label_y = grid_y + grid_h + 4;
+        draw_text(
+            &mut img,
+            grid_x,
+            az_label_y,
+            &format!("{:.1}", az_min as f64 * self.resolution),
+        );
+        let az_max_label = format!("{:.1}", az_max as f64 * self.resolution);
+        draw_text(
+            &mut img,
+            (grid_x + grid_w).saturating_sub(text_width(&az_max_label)),
+            az_label_y,
+            &az_max_label,
+        );
+        draw_text(
+            &mut img,
+            2,
+            grid_y,
+            &format!("{:.1}", el_max as f64 * self.resolution),
+        );
+        draw_text(
+            &mut img,
+            2,
+            grid_y + grid_h - GLYPH_HEIGHT,
+            &format!("{:.1}", el_min as f64 * self.resolution),
+        );
+
+        img.save(path)
+    }
+
+    /// (min, max) cell indices over azimuth and elevation
+    fn index_bounds(&self) -> Option<((i64, i64), (i64, i64))> {
+        let az_min = self.cells.keys().map(|k| k.0).min()?;
+        let az_max = self.cells.keys().map(|k| k.0).max()?;
+        let el_min = self.cells.keys().map(|k| k.1).min()?;
+        let el_max = self.cells.keys().map(|k| k.1).max()?;
+        Some(((az_min, az_max), (el_min, el_max)))
+    }
+}
+
+fn fill_rect(img: &mut RgbImage, x: u32, y: u32, w: u32, h: u32, color: [u8; 3]) {
+    for py in y..(y + h).min(img.height()) {
+        for px in x..(x + w).min(img.width()) {
+            img.put_pixel(px, py, Rgb(color));
+        }
+    }
+}
+
+/// size of a single pixel of the label font
+const GLYPH_SCALE: u32 = 2;
+const GLYPH_HEIGHT: u32 = 5 * GLYPH_SCALE;
+
+/// 3x5 bitmap glyphs for the characters used in numeric labels,
+/// one row per entry with the leftmost pixel in the highest bit
+fn glyph(ch: char) -> [u8; 5] {
+    match ch {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
+
+fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * 4 * GLYPH_SCALE
+}
+
+fn draw_text(img: &mut RgbImage, x: u32, y: u32, text: &str) {
+    for (i, ch) in text.chars().enumerate() {
+        let gx = x + i as u32 * 4 * GLYPH_SCALE;
+        for (row, bits) in glyph(ch).iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) != 0 {
+                    fill_rect(
+                        img,
+                        gx + col * GLYPH_SCALE,
+                        y + row as u32 * GLYPH_SCALE,
+                        GLYPH_SCALE,
+                        GLYPH_SCALE,
+                        [0, 0, 0],
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// How a normalized power is colored on the heatmap and the sky plot, see
+/// `--color-ramp`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ColorRamp {
+    /// dark blue → green → yellow, readable with color blindness and when
+    /// printed in gray
+    #[default]
+    Viridis,
+    /// blue → green → red with more contrast than viridis, but not as easy
+    /// on color blindness
+    Turbo,
+    /// black → white, for printing
+    Grayscale,
+    /// blue → green → yellow → red
+    Rainbow,
+}
+
+/// viridis at every eighth of the ramp, interpolated in between
+const VIRIDIS: [[f64; 3]; 9] = [
+    [68.0, 1.0, 84.0],
+    [71.0, 44.0, 122.0],
+    [59.0, 82.0, 139.0],
+    [44.0, 114.0, 142.0],
+    [33.0, 145.0, 140.0],
+    [40.0, 174.0, 128.0],
+    [94.0, 201.0, 98.0],
+    [173.0, 220.0, 48.0],
+    [253.0, 231.0, 37.0],
+];
+
+impl ColorRamp {
+    /// Maps a normalized power (0..1) to a color, clamping outside of that.
+    pub fn color(self, t: f64) -> Color {
+        let [r, g, b] = self.rgb(t);
+        Color::Rgb(r, g, b)
+    }
+
+    pub fn rgb(self, t: f64) -> [u8; 3] {
+        let t = t.clamp(0.0, 1.0);
+        let [r, g, b] = match self {
+            ColorRamp::Viridis => {
+                let position = t * (VIRIDIS.len() - 1) as f64;
+                let i = (position as usize).min(VIRIDIS.len() - 2);
+                let k = position - i as f64;
+                let (low, high) = (VIRIDIS[i], VIRIDIS[i + 1]);
+                [0, 1, 2].map(|c| (low[c] + (high[c] - low[c]) * k) / 255.0)
+            }
+            // polynomial fit of turbo by its author, Anton Mikhailov
+            ColorRamp::Turbo => [
+                0.13572138
+                    + t * (4.6153926
+                        + t * (-42.66032258
+                            + t * (132.13108234 + t * (-152.94239396 + t * 59.28637943)))),
+                0.09140261
+                    + t * (2.19418839
+                        + t * (4.84296658
+                            + t * (-14.18503333 + t * (4.27729857 + t * 2.82956604)))),
+                0.1066733
+                    + t * (12.64194608
+                        + t * (-60.58204836
+                            + t * (110.36276771 + t * (-89.90310912 + t * 27.34824973)))),
+            ],
+            ColorRamp::Grayscale => [t, t, t],
+            ColorRamp::Rainbow => {
+                if t < 1.0 / 3.0 {
+                    let k = t * 3.0;
+                    [0.0, k, 1.0 - k]
+                } else if t < 2.0 / 3.0 {
+                    let k = (t - 1.0 / 3.0) * 3.0;
+                    [k, 1.0, 0.0]
+                } else {
+                    let k = (t - 2.0 / 3.0) * 3.0;
+                    [1.0, 1.0 - k, 0.0]
+                }
+            }
+        };
+        [r, g, b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+}
+
+impl Widget for &Heatmap {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut title = match self.peak() {
+            Some((az, el, power)) => {
+                format!(" Heatmap  peak {:.1} @ {:.1}/{:.1} ", power, az, el)
+            }
+            None => " Heatmap ".to_string(),
+        };
+        if let Some((az, el)) = self.reference {
+            title.push_str(&format!(" ref {:.1}/{:.1} ", az, el));
+        }
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let (Some(((az_min, az_max), (el_min, el_max))), Some((p_min, p_max))) =
+            (self.index_bounds(), self.power_range())
+        else {
+            return;
+        };
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+
+        let az_cells = (az_max as f64 - az_min as f64) + 1.0;
+        let el_cells = (el_max as f64 - el_min as f64) + 1.0;
+        let peak = self
+            .peak()
+            .map(|(az, el, _)| (self.cell_index(az), self.cell_index(el)));
+
+        for y in 0..inner.height {
+            for x in 0..inner.width {
+                // azimuth grows to the right, elevation grows upwards
+                let az = az_min.saturating_add((x as f64 * az_cells / inner.width as f64) as i64);
+                let el = el_max.saturating_sub((y as f64 * el_cells / inner.height as f64) as i64);
+
+                let Some(&power) = self.cells.get(&(az, el)) else {
+                    continue;
+                };
+                let t = if p_max > p_min {
+                    (power - p_min) / (p_max - p_min)
+                } else {
+                    1.0
+                };
+
+                let cell = &mut buf[(inner.x + x, inner.y + y)];
+                cell.set_style(Style::default().bg(self.ramp.color(t)));
+                if peak == Some((az, el)) {
+                    cell.set_char('*');
+                }
+            }
+        }
+
+        if let Some((az, el)) = self.reference {
+            let (az, el) = (self.cell_index(az), self.cell_index(el));
+            if (az_min..=az_max).contains(&az) && (el_min..=el_max).contains(&el) {
+                let x = ((az - az_min) as f64 * inner.width as f64 / az_cells) as u16;
+                let y = ((el_max - el) as f64 * inner.height as f64 / el_cells) as u16;
+                buf[(inner.x + x, inner.y + y)]
+                    .set_char('R')
+                    .set_style(Style::default().fg(Color::White).bg(Color::Black));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_ramps() {
+        assert_eq!(ColorRamp::Viridis.rgb(0.0), [68, 1, 84]);
+        assert_eq!(ColorRamp::Viridis.rgb(1.0), [253, 231, 37]);
+        // halfway between the first two stops
+        assert_eq!(ColorRamp::Viridis.rgb(1.0 / 16.0), [70, 23, 103]);
+        assert_eq!(ColorRamp::Grayscale.rgb(0.5), [128, 128, 128]);
+        assert_eq!(ColorRamp::Rainbow.rgb(0.0), [0, 0, 255]);
+        assert_eq!(ColorRamp::Rainbow.rgb(1.0), [255, 0, 0]);
+
+        // out of range powers clamp to the ends
+        for ramp in ColorRamp::value_variants() {
+            assert_eq!(ramp.rgb(-1.0), ramp.rgb(0.0));
+            assert_eq!(ramp.rgb(2.0), ramp.rgb(1.0));
+        }
+        // turbo is blue near the bottom and red near the top
+        let [r, _, b] = ColorRamp::Turbo.rgb(0.1);
+        assert!(b > r);
+        let [r, _, b] = ColorRamp::Turbo.rgb(0.9);
+        assert!(r > b);
+    }
+
+    #[test]
+    fn test_export_png_size() {
+        let path =
+            std::env::temp_dir().join(format!("tailgaters_heatmap_{}.png", std::process::id()));
+
+        let mut heatmap = Heatmap::new(1.0);
+        heatmap.add(180.0, 30.0, 3100.0);
+        heatmap.add(189.0, 34.0, 3200.0);
+        heatmap.export_png(&path).unwrap();
+        let (width, height) = image::image_dimensions(&path).unwrap();
+        assert_eq!((width, height), (40 + 80 + 20 + 16 + 40, 20 + 64 + 40));
+
+        // a fine step over the whole sky stays a sane size
+        let mut heatmap = Heatmap::new(0.001);
+        heatmap.add(0.0, 0.0, 3100.0);
+        heatmap.add(359.0, 90.0, 3200.0);
+        heatmap.export_png(&path).unwrap();
+        let (width, height) = image::image_dimensions(&path).unwrap();
+        assert!(
+            width <= 4096 + 116 && height <= 4096 + 60,
+            "{}x{}",
+            width,
+            height
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_set_resolution() {
+        let mut heatmap = Heatmap::new(1.0);
+        heatmap.add(180.0, 30.0, 3100.0);
+        heatmap.add(181.0, 30.0, 3200.0);
+        heatmap.add(185.0, 31.0, 3300.0);
+
+        heatmap.set_resolution(2.5);
+        assert_eq!(heatmap.cells().count(), 2);
+        assert_eq!(heatmap.power_at(180.0, 30.0), Some(3200.0));
+        assert_eq!(heatmap.power_at(185.0, 30.0), Some(3300.0));
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender};
+use log::{info, warn};
+
+use crate::RfPowerSample;
+
+/// points are written at least this often
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// or as soon as this many are waiting
+const BATCH_SIZE: usize = 500;
+/// points kept while the server is unreachable, the oldest are dropped beyond this
+const MAX_BACKLOG: usize = 50_000;
+/// after a failed write the next one waits `FLUSH_INTERVAL`, doubling with
+/// every further failure up to this
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// Writes rf samples to InfluxDB in the line protocol, as measurement `rf`
+/// tagged with the session, batched and flushed from a background thread.
+pub struct InfluxWriter {
+    lines: Sender<String>,
+    session: String,
+}
+
+impl InfluxWriter {
+    /// `url` is the full write endpoint, e.g.
+    /// `http://localhost:8086/api/v2/write?org=home&bucket=rf&precision=ns`
+    pub fn start(url: String, token: Option<String>, session: String) -> InfluxWriter {
+        info!("Writing samples to {}", url);
+
+        let (lines, receiver) = crossbeam::channel::unbounded();
+        thread::spawn(move || flush_loop(receiver, &url, token.as_deref()));
+
+        InfluxWriter { lines, session }
+    }
+
+    pub fn publish(&self, sample: &RfPowerSample) {
+        // the flush thread only stops along with the app
+        let _ = self.lines.send(line_protocol(sample, &self.session));
+    }
+}
+
+fn flush_loop(receiver: Receiver<String>, url: &str, token: Option<&str>) {
+    let mut batch: Vec<String> = vec![];
+    let mut last_flush = Instant::now();
+    // while the server is failing, when to try it again and how long the
+    // wait after that will be
+    let mut retry: Option<(Instant, Duration)> = None;
+    loop {
+        let disconnected = match receiver.recv_timeout(FLUSH_INTERVAL) {
+            Ok(line) => {
+                batch.push(line);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+
+        let due = match retry {
+            // a full batch doesn't make a server that's down come back sooner
+            Some((at, _)) => Instant::now() >= at,
+            None => batch.len() >= BATCH_SIZE || last_flush.elapsed() >= FLUSH_INTERVAL,
+        };
+        if !batch.is_empty() && (due || disconnected) {
+            match write_batch(url, token, &batch) {
+                Ok(()) => {
+                    batch.clear();
+                    retry = None;
+                }
+                Err(e) => {
+                    let delay = retry.map_or(FLUSH_INTERVAL, |(_, delay)| delay);
+                    warn!(
+                        "Could not write {} points to InfluxDB, retrying in {} s: {}",
+                        batch.len(),
+                        delay.as_secs(),
+                        e
+                    );
+                    retry = Some((Instant::now() + delay, (delay * 2).min(MAX_RETRY_DELAY)));
+                    if batch.len() > MAX_BACKLOG {
+                        batch.drain(..batch.len() - MAX_BACKLOG);
+                    }
+                }
+            }
+            last_flush = Instant::now();
+        }
+
+        if disconnected {
+            return;
+        }
+    }
+}
+
+fn write_batch(url: &str, token: Option<&str>, batch: &[String]) -> Result<(), Box<ureq::Error>> {
+    let mut request = ureq::post(url).set("Content-Type", "text/plain; charset=utf-8");
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("Token {}", token));
+    }
+    request.send_string(&batch.join("\n"))?;
+    Ok(())
+}
+
+/// tag values can't contain unescaped commas, spaces or equal signs
+fn escape_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+        .replace('=', "\\=")
+}
+
+fn line_protocol(sample: &RfPowerSample, session: &str) -> String {
+    let mut tags = format!("session={}", escape_tag(session));
+    if let Some(target) = &sample.target {
+        tags.push_str(&format!(",target={}", escape_tag(target)));
+    }
+
+    let timestamp_ns = sample
+        .timestamp
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    format!(
+        "rf,{} power={},azimuth={},elevation={} {}",
+        tags, sample.power, sample.azimuth, sample.elevation, timestamp_ns
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_protocol() {
+        let sample = RfPowerSample {
+            power: 3142.5,
+            azimuth: 180.0,
+            elevation: 30.5,
+            elapsed: Duration::from_secs(1),
+            timestamp: std::time::UNIX_EPOCH + Duration::from_secs(2),
+            target: Some("Astra 19.2E, east".to_string()),
+            power_stddev: None,
+        };
+        assert_eq!(
+            line_protocol(&sample, "7"),
+            "rf,session=7,target=Astra\\ 19.2E\\,\\ east power=3142.5,azimuth=180,elevation=30.5 2000000000"
+        );
+    }
+}
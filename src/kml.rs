@@ -0,0 +1,76 @@
+use std::{fs::File, io, io::Write, path::Path};
+
+use crate::{dish_actions::GpsLocation, heatmap};
+
+/// length of the drawn pointing vectors, in meters
+const RAY_LENGTH_M: f64 = 1000.0;
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Writes the scan points as KML: a placemark for the dish, plus one line per
+/// point along its pointing direction, colored by signal strength.
+pub fn write_kml(
+    path: &Path,
+    location: GpsLocation,
+    samples: impl Iterator<Item = (f64, f64, f64)>,
+) -> io::Result<()> {
+    let samples: Vec<(f64, f64, f64)> = samples.collect();
+    let min = samples.iter().map(|s| s.2).fold(f64::INFINITY, f64::min);
+    let max = samples
+        .iter()
+        .map(|s| s.2)
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    let mut file = File::create(path)?;
+    writeln!(file, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(file, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(file, "<Document>")?;
+    writeln!(file, "<name>{}</name>", path.display())?;
+    writeln!(
+        file,
+        "<Placemark><name>Dish</name><Point><coordinates>{},{},0</coordinates></Point></Placemark>",
+        location.longitude, location.latitude
+    )?;
+
+    for (azimuth, elevation, power) in samples {
+        let t = if max > min {
+            (power - min) / (max - min)
+        } else {
+            1.0
+        };
+        let [r, g, b] = heatmap::ColorRamp::Rainbow.rgb(t);
+
+        // flat earth is plenty for a kilometer long ray
+        let horizontal = RAY_LENGTH_M * elevation.to_radians().cos();
+        let height = RAY_LENGTH_M * elevation.to_radians().sin();
+        let d_lat = horizontal * azimuth.to_radians().cos() / EARTH_RADIUS_M;
+        let d_lon = horizontal * azimuth.to_radians().sin()
+            / (EARTH_RADIUS_M * location.latitude.to_radians().cos());
+
+        writeln!(file, "<Placemark>")?;
+        writeln!(
+            file,
+            "<name>{:.1}/{:.1}: {:.1}</name>",
+            azimuth, elevation, power
+        )?;
+        // kml colors are aabbggrr
+        writeln!(
+            file,
+            "<Style><LineStyle><color>ff{:02x}{:02x}{:02x}</color><width>2</width></LineStyle></Style>",
+            b, g, r
+        )?;
+        writeln!(
+            file,
+            "<LineString><altitudeMode>relativeToGround</altitudeMode><coordinates>{},{},0 {},{},{}</coordinates></LineString>",
+            location.longitude,
+            location.latitude,
+            location.longitude + d_lon.to_degrees(),
+            location.latitude + d_lat.to_degrees(),
+            height
+        )?;
+        writeln!(file, "</Placemark>")?;
+    }
+
+    writeln!(file, "</Document>")?;
+    writeln!(file, "</kml>")?;
+    Ok(())
+}
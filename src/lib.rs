@@ -0,0 +1,70 @@
+//! Control of the dish over its serial console: the firmware's commands and
+//! replies (`dish_driver`), the serial connection and the dish state
+//! (`dish_controller`) and the scans and moves built on them
+//! (`dish_actions`). The TUI in `main.rs` is one user of it, `Dish` is the
+//! simple way to drive a dish from another program, and with the `async`
+//! feature `AsyncDishSerialController` one to await from tokio.
+
+use std::time::Duration;
+
+#[cfg(feature = "async")]
+pub mod async_controller;
+pub mod dish;
+pub mod dish_actions;
+pub mod dish_controller;
+pub mod dish_driver;
+pub mod error;
+pub mod npy;
+pub mod output_file;
+pub mod simulator;
+
+pub use dish::Dish;
+use dish_driver::DishResponse;
+
+/// Messages between the threads: the serial readers, the actions and the
+/// app that owns the dishes.
+#[derive(Debug)]
+pub enum GlobalBus {
+    /// a command for the dishes, every dish gets it so they move together
+    DishCommand(dish_driver::DishCommand),
+    /// halt every dish where it is, see `DishState::stop_commands`
+    StopMotion,
+    /// a position poll for the dish with the given index only
+    PollCommand(usize, dish_driver::DishCommand),
+    /// a response from the dish with the given index, 0 is the first `--port`
+    DishResponse(usize, DishResponse),
+    RfPowerSample(RfPowerSample),
+    ActionProgress {
+        done: usize,
+        total: usize,
+    },
+    /// a Scan2d is about to take its first reading, with the plan target if
+    /// any and its grid to fill for `--export-npy`
+    ScanStarted(Option<String>, Option<npy::ScanGrid>),
+    /// a Scan2d went through all of its points
+    ScanFinished,
+    /// the running action returned, whether it finished or was cancelled
+    ActionFinished,
+    /// a line from the dish that isn't a known response, prompt or echo
+    UnparsedLine(usize, String),
+    /// every line received from the dish, as it came, for the serial panel
+    RawLine(usize, String),
+    /// an action finished the session, e.g. `DishAction::Shutdown`
+    Quit,
+    Update,
+}
+
+#[derive(Clone, Debug)]
+pub struct RfPowerSample {
+    pub power: f64,
+    pub azimuth: f64,
+    pub elevation: f64,
+    /// time since the session started, the csv's `elapsed_s`
+    pub elapsed: Duration,
+    /// wall clock time of the sample
+    pub timestamp: std::time::SystemTime,
+    /// plan target being scanned when the sample was taken
+    pub target: Option<String>,
+    /// spread of the readings `power` is the mean of, with `--samples-per-point`
+    pub power_stddev: Option<f64>,
+}
@@ -1,28 +1,71 @@
-use clap::Parser;
-use dish_controller::{DishSerialController, DishState};
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use dish_controller::{
+    AzimuthCalibration, DishController, DishSerialController, DishState, ElevationCalibration,
+};
 use dish_driver::DishResponse;
-use log::{info, trace, warn, LevelFilter};
+use log::{debug, error, info, trace, warn, LevelFilter};
+use output_file::OutputFile;
 use ratatui::buffer::Buffer;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Style, Stylize};
 use ratatui::text::Line;
-use ratatui::widgets::{Block, Paragraph, Widget};
-use std::fs::OpenOptions;
-use std::io::{self, Write};
+use ratatui::widgets::{Block, Gauge, Paragraph, Sparkline, Widget};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
-use tui_logger::{init_logger, set_default_level, TuiLoggerSmartWidget};
+use tailgaters::{
+    dish_actions, dish_controller, dish_driver, error, npy, output_file, simulator, GlobalBus,
+    RfPowerSample,
+};
+use tui_logger::{
+    init_logger, set_default_level, set_log_file, TuiLoggerFile, TuiLoggerSmartWidget,
+};
 
-mod dish_actions;
-mod dish_controller;
-mod dish_driver;
+mod bus_queue;
+mod command_log;
+mod config;
+mod database;
+mod form;
+mod heatmap;
+mod influx;
+mod kml;
+mod metrics;
+mod mqtt;
+mod plan;
+mod polar;
+mod rotctld;
+mod scan_stats;
+mod ws;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
-struct Cli {
-    #[arg(short, long, default_value = "/dev/ttyACM0")]
-    port: String,
+pub struct Cli {
+    /// toml file with defaults for the options below
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// serial port of the dish, repeat it to drive several dishes at once.
+    /// Detected when there's a single USB serial device
+    #[arg(short, long)]
+    port: Vec<String>,
     #[arg(short, long, default_value = "9600")]
     baudrate: u32,
+    /// 5, 6, 7 or 8
+    #[arg(long, default_value = "8", value_parser = dish_controller::parse_data_bits)]
+    data_bits: serialport::DataBits,
+    /// none, odd or even
+    #[arg(long, default_value = "none", value_parser = dish_controller::parse_parity)]
+    parity: serialport::Parity,
+    /// 1 or 2
+    #[arg(long, default_value = "1", value_parser = dish_controller::parse_stop_bits)]
+    stop_bits: serialport::StopBits,
+    /// none, software (XON/XOFF) or hardware (RTS/CTS)
+    #[arg(long, default_value = "none", value_parser = dish_controller::parse_flow_control)]
+    flow_control: serialport::FlowControl,
     #[arg(long, default_value = "90")]
     az_start: i32,
     #[arg(long, default_value = "270")]
@@ -31,299 +74,2457 @@ struct Cli {
     el_start: i32,
     #[arg(long, default_value = "70")]
     el_end: i32,
+    /// unit of --el-start and --el-end. With `count` the elevation is also
+    /// moved by count, skipping the firmware's conversion from degrees
+    #[arg(long, value_enum, default_value = "deg")]
+    el_unit: ElevationUnit,
     #[arg(long, default_value = "1")]
     step: f64,
+    /// time to wait for the rf reading at each scan point, in milliseconds
+    #[arg(long, default_value = "1000")]
+    dwell_ms: u64,
+    #[arg(long)]
+    scan: bool,
+    /// keep the dish peaked on the signal after startup (and after the scan, if any)
+    #[arg(long)]
+    track: bool,
+    /// offset used to probe around the current position while tracking, in degrees
+    #[arg(long, default_value = "0.5")]
+    track_dither: f64,
+    /// pause between tracking corrections, in milliseconds
+    #[arg(long, default_value = "5000")]
+    track_period_ms: u64,
+    /// sit at <azimuth>,<elevation> and keep taking rf readings, after the
+    /// scans and waypoints if any
+    #[arg(long)]
+    monitor: Option<dish_actions::DishPosition>,
+    /// stop monitoring after this many minutes, runs until cancelled otherwise
+    #[arg(long)]
+    monitor_minutes: Option<u64>,
+    /// time between the readings while monitoring, in milliseconds
+    #[arg(long, default_value = "1000")]
+    monitor_interval_ms: u64,
+    /// half width of the box the C key searches for the peak in, in degrees
+    #[arg(long, default_value = "2.0")]
+    peak_box: f64,
+    /// spacing of the readings taken in that box, in degrees
+    #[arg(long, default_value = "1.0")]
+    peak_step: f64,
+    /// measure the azimuth beamwidth of the peak at "az,el", after the scans
+    /// and waypoints
+    #[arg(long)]
+    beamwidth: Option<dish_actions::DishPosition>,
+    /// spacing of the readings taken while measuring the beamwidth, also
+    /// with the B key, in degrees
+    #[arg(long, default_value = "0.5")]
+    beamwidth_step: f64,
+    /// replay a recorded rf_power csv instead of connecting to the dish
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// rf_power csv of an earlier scan whose peak is marked on the heatmap
+    /// and sky plot, to check the pointing after moving the dish
+    #[arg(long)]
+    reference: Option<PathBuf>,
+    /// colors of the heatmap, the sky plot and the heatmap png
+    #[arg(long, value_enum, default_value = "viridis")]
+    color_ramp: heatmap::ColorRamp,
+    /// file to record rf samples to, defaults to rf_power_<timestamp>.<format>
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// directory for the default output file
+    #[arg(long, default_value = ".")]
+    output_dir: PathBuf,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    format: OutputFormat,
+    /// start a new output file, with an index suffix, every this many minutes
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    rotate_minutes: Option<u64>,
+    /// start a new output file once the current one reaches this many megabytes
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    rotate_mb: Option<u64>,
+    /// number of recent rf samples shown in the signal chart
+    #[arg(long, default_value = "200")]
+    history_len: usize,
+    /// save the heatmap to this png when a scan finishes
+    #[arg(long)]
+    export_png: Option<PathBuf>,
+    /// serve the hamlib rotctld protocol on this tcp port
+    #[arg(long)]
+    rotctld_port: Option<u16>,
+    /// address the rotctld server listens on. It has no authentication, so
+    /// only bind beyond localhost on a network you trust
+    #[arg(long, default_value = "127.0.0.1")]
+    rotctld_bind: std::net::IpAddr,
+    /// publish rf samples to this MQTT broker, host or host:port
+    #[arg(long)]
+    mqtt_broker: Option<String>,
+    #[arg(long, default_value = "tailgaters/rf_power")]
+    mqtt_topic: String,
+    /// expose prometheus metrics over http on this port
+    #[arg(long)]
+    metrics_port: Option<u16>,
+    /// also record samples to this sqlite database
+    #[arg(long)]
+    sqlite: Option<PathBuf>,
+    /// latitude of the dish, in degrees
+    #[arg(long, allow_negative_numbers = true)]
+    latitude: Option<f64>,
+    /// longitude of the dish, in degrees
+    #[arg(long, allow_negative_numbers = true)]
+    longitude: Option<f64>,
+    /// end scans at the first reading at least this strong and leave the dish there
+    #[arg(long)]
+    stop_on_power: Option<f64>,
+    /// rf readings averaged into each scan sample, recorded with their stddev
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    samples_per_point: u32,
+    /// orbital longitude of a geostationary satellite to show the look angles
+    /// of, needs the dish location
+    #[arg(long, allow_negative_numbers = true)]
+    satellite_lon: Option<f64>,
+    /// save the scan as kml pointing vectors when a scan finishes, needs the dish location
+    #[arg(long)]
+    export_kml: Option<PathBuf>,
+    /// save the power of each finished scan on its az/el grid as a NumPy .npy
+    /// array, with the axes next to it as <name>_az.npy and <name>_el.npy
+    #[arg(long)]
+    export_npy: Option<PathBuf>,
+    /// append a json line with the statistics of each finished scan to this file
+    #[arg(long)]
+    scan_summary: Option<PathBuf>,
+    /// run against a simulated dish instead of the serial port
+    #[arg(long)]
+    simulate: bool,
+    /// play back raw output captured from the serial port instead of opening it
+    #[arg(long)]
+    serial_log: Option<PathBuf>,
+    /// lines per second read from --serial-log
+    #[arg(long, default_value = "20")]
+    serial_log_rate: f64,
+    /// append everything received on the serial port to this file, raw and timestamped
+    #[arg(long)]
+    record_serial: Option<PathBuf>,
+    /// append every command sent to the dishes to this file, with timestamps
+    #[arg(long)]
+    command_log: Option<PathBuf>,
+    /// include the position polls in --command-log, several lines a second
+    #[arg(long)]
+    command_log_polls: bool,
+    /// azimuth of the simulated signal
+    #[arg(long, default_value = "180")]
+    sim_peak_az: f64,
+    /// elevation of the simulated signal
+    #[arg(long, default_value = "40")]
+    sim_peak_el: f64,
+    /// elevation readback at the lower stop (0°)
+    #[arg(long, default_value = "334")]
+    el_count_min: i32,
+    /// elevation readback at the upper stop (--el-angle-max)
+    #[arg(long, default_value = "1487")]
+    el_count_max: i32,
+    /// elevation angle of the upper stop
+    #[arg(long, default_value = "70")]
+    el_angle_max: f64,
+    /// azimuth count at 0°, derives the azimuth from the count instead of
+    /// trusting the firmware's angle
+    #[arg(long)]
+    az_count_offset: Option<i32>,
+    /// azimuth counts per degree, same as --az-count-offset
+    #[arg(long)]
+    az_counts_per_degree: Option<f64>,
+    /// degrees added to the azimuth read from the dish to get the real sky
+    /// azimuth, and taken off again when commanding it
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    az_offset: f64,
+    /// degrees added to the elevation read from the dish, like --az-offset
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    el_offset: f64,
+    /// azimuth the dish is driven to by the stow key
+    #[arg(long, default_value = "0")]
+    stow_az: f64,
+    /// seconds of rf readings averaged into each sample
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    rf_watch: u32,
+    /// hold the elevation with the firmware's elevmt instead of a one-shot elangle
+    #[arg(long)]
+    elevation_maintain: bool,
+    /// rotate with the firmware's azim (heading) instead of azangle
+    #[arg(long)]
+    azimuth_heading: bool,
+    /// toml or json file listing targets to scan one after the other
+    #[arg(long)]
+    plan: Option<PathBuf>,
+    /// file of `azimuth, elevation` lines to take a reading at, one after the other
+    #[arg(long)]
+    waypoints: Option<PathBuf>,
+    /// weight of the newest reading in the displayed signal average, 1 disables it
+    #[arg(long, default_value = "0.3")]
+    smoothing: f64,
+    /// rf readings above this are flagged as saturated and taken again
+    #[arg(long, default_value = "5000")]
+    saturation_limit: f64,
+    /// how long to wait before taking a saturated reading again, in
+    /// milliseconds
+    #[arg(long, default_value = "1000")]
+    saturation_cooldown_ms: u64,
+    /// serve a live json feed of the state and samples over WebSocket on this port
+    #[arg(long)]
+    ws_port: Option<u16>,
+    /// InfluxDB write endpoint to send the samples to, in the line protocol
+    #[arg(long)]
+    influx_url: Option<String>,
+    /// API token for --influx-url
+    #[arg(long)]
+    influx_token: Option<String>,
+    /// stow (and with --shutdown-reset, reset) the dish and quit once the other actions are done
+    #[arg(long)]
+    shutdown: bool,
+    /// reset the firmware as part of --shutdown or the Q key
+    #[arg(long)]
+    shutdown_reset: bool,
+    /// degrees from the target within which a move counts as arrived
+    #[arg(long, default_value = "2.0")]
+    position_tolerance: f64,
+    /// time given to a move before it's sent again
+    #[arg(long, default_value = "15000")]
+    position_timeout_ms: u64,
+    /// times a timed out move is sent again before giving up on the point
+    #[arg(long, default_value = "1")]
+    position_retries: u32,
+    /// before each retry of a move, nudge the stuck axis the other way first
     #[arg(long)]
+    rehome: bool,
+    /// lowest azimuth the dish is ever commanded to, moves beyond the limits are clamped
+    #[arg(long, default_value = "0")]
+    az_min: f64,
+    /// highest azimuth the dish is ever commanded to
+    #[arg(long, default_value = "360")]
+    az_max: f64,
+    /// lowest elevation the dish is ever commanded to
+    #[arg(long, default_value = "0")]
+    el_min: f64,
+    /// highest elevation the dish is ever commanded to, defaults to --el-angle-max
+    #[arg(long)]
+    el_max: Option<f64>,
+    /// degrees the dish is moved by Shift+arrow keys
+    #[arg(long, default_value = "1.0")]
+    move_step: f64,
+    /// find the elevation stops before anything else and calibrate against them
+    #[arg(long)]
+    calibrate_elevation: bool,
+    /// check that the firmware answers and both motors move before anything
+    /// else, dropping the queued actions if not
+    #[arg(long)]
+    selftest: bool,
+    /// run without the TUI, logging to stderr, and quit once the queued
+    /// actions are done. For cron jobs and services
+    #[arg(long)]
+    headless: bool,
+    /// also append the log to this file, with timestamps
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+    /// most verbose level logged: error, warn, info, debug or trace. Defaults
+    /// to debug, info with --headless
+    #[arg(long)]
+    log_level: Option<LevelFilter>,
+    /// flag the link as down when the dish doesn't answer for this long, 0
+    /// disables the watchdog
+    #[arg(long, default_value = "5000")]
+    watchdog_ms: u64,
+    /// reopen the serial port while the watchdog has the link down
+    #[arg(long)]
+    reconnect: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Csv,
+    /// one json object per line
+    Jsonl,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum ElevationUnit {
+    Deg,
+    /// the readback of the elevation sensor
+    Count,
+}
+
+/// What the log view prints in front of each message, toggled with 1-4 to
+/// make room for the messages on narrow terminals
+#[derive(Clone, Copy, Debug)]
+struct LogColumns {
+    target: bool,
+    file: bool,
+    line: bool,
+    timestamp: LogTimestamp,
+}
+
+impl Default for LogColumns {
+    fn default() -> Self {
+        LogColumns {
+            target: true,
+            file: true,
+            line: true,
+            timestamp: LogTimestamp::Seconds,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LogTimestamp {
+    Millis,
+    Seconds,
+    Off,
+}
+
+impl LogTimestamp {
+    fn next(self) -> LogTimestamp {
+        match self {
+            LogTimestamp::Millis => LogTimestamp::Seconds,
+            LogTimestamp::Seconds => LogTimestamp::Off,
+            LogTimestamp::Off => LogTimestamp::Millis,
+        }
+    }
+
+    fn format(self) -> Option<String> {
+        match self {
+            LogTimestamp::Millis => Some("%H:%M:%S%.3f".to_string()),
+            LogTimestamp::Seconds => Some("%H:%M:%S".to_string()),
+            LogTimestamp::Off => None,
+        }
+    }
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{DefaultTerminal, Frame};
+
+/// An `RfPowerSample` as written to jsonl output
+#[derive(Serialize)]
+struct RfPowerRecord {
+    power: f64,
+    azimuth: f64,
+    elevation: f64,
+    /// seconds since the unix epoch
+    timestamp: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    power_stddev: Option<f64>,
+}
+
+impl From<&RfPowerSample> for RfPowerRecord {
+    fn from(sample: &RfPowerSample) -> Self {
+        RfPowerRecord {
+            power: sample.power,
+            azimuth: sample.azimuth,
+            elevation: sample.elevation,
+            timestamp: sample
+                .timestamp
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            target: sample.target.clone(),
+            power_stddev: sample.power_stddev,
+        }
+    }
+}
+
+/// Provenance of a recording, written once at the top of the csv (or next to
+/// a jsonl file) so old results stay self-describing.
+#[derive(Serialize)]
+struct SessionMetadata {
+    /// seconds since the unix epoch
+    started_at: u64,
+    firmware_version: Option<String>,
+    port: String,
+    baudrate: u32,
     scan: bool,
+    az_start: i32,
+    az_end: i32,
+    el_start: i32,
+    el_end: i32,
+    el_unit: ElevationUnit,
+    step: f64,
+    dwell_ms: u64,
+    rf_watch: u32,
+    plan: Option<PathBuf>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+/// lines kept for the serial panel
+const RAW_LINES_KEPT: usize = 500;
+
+/// how often the UI is redrawn when nothing happens
+const TICK_INTERVAL: Duration = Duration::from_millis(250);
+/// shortest time between redraws; the events of a burst, e.g. fast rf
+/// readings, are all handled and recorded but drawn together
+const MIN_DRAW_INTERVAL: Duration = Duration::from_millis(50);
+/// time between attempts to reopen the port of a dish that went silent, the
+/// first one usually fails while the old reader still holds the port
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+/// how long the panel flags a nudge refused at a soft limit
+const LIMIT_FLASH: Duration = Duration::from_millis(700);
+
+/// A dish given with a second (third, ...) `--port`. It's sent the same
+/// moves as the first one and records its own readings, while the actions
+/// and the integrations only follow the first dish.
+struct ExtraDish {
+    controller: Box<dyn DishController>,
+    state: DishState,
+    output_file: OutputFile,
+}
+
+impl ExtraDish {
+    fn output_file(&mut self, format: OutputFormat) -> io::Result<&mut File> {
+        self.output_file.get(|file, _| {
+            if format == OutputFormat::Csv {
+                writeln!(file, "{}", CSV_HEADER)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// the output file of the dish with the given index, e.g. rf_power_1741745129_dish2.csv
+/// for the second one
+fn dish_output_path(path: &std::path::Path, dish: usize) -> PathBuf {
+    output_file::suffixed_path(path, &format!("dish{}", dish + 1))
+}
+
+const CSV_HEADER: &str = "elapsed_s,power,azimuth,elevation,target,power_stddev";
+
+fn write_sample(file: &mut File, format: OutputFormat, sample: &RfPowerSample) -> io::Result<()> {
+    match format {
+        OutputFormat::Csv => writeln!(
+            file,
+            "{:.3},{},{},{},{},{}",
+            sample.elapsed.as_secs_f64(),
+            sample.power,
+            sample.azimuth,
+            sample.elevation,
+            // a comma in the name would shift the columns
+            sample
+                .target
+                .as_deref()
+                .unwrap_or_default()
+                .replace(',', " "),
+            sample
+                .power_stddev
+                .map(|stddev| stddev.to_string())
+                .unwrap_or_default()
+        ),
+        OutputFormat::Jsonl => {
+            serde_json::to_writer(&mut *file, &RfPowerRecord::from(sample))?;
+            writeln!(file)
+        }
+    }
+}
+
+/// csv files get the metadata as `# key: value` comment lines, jsonl
+/// ones a `.meta.json` sidecar so every line stays a sample
+fn write_metadata(
+    metadata: &SessionMetadata,
+    format: OutputFormat,
+    file: &mut File,
+    path: &std::path::Path,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Csv => {
+            let serde_json::Value::Object(fields) = serde_json::to_value(metadata)? else {
+                return Ok(());
+            };
+            for (key, value) in fields {
+                match value {
+                    serde_json::Value::Null => {}
+                    serde_json::Value::String(value) => writeln!(file, "# {}: {}", key, value)?,
+                    value => writeln!(file, "# {}: {}", key, value)?,
+                }
+            }
+            Ok(())
+        }
+        OutputFormat::Jsonl => {
+            let sidecar = File::create(path.with_extension("meta.json"))?;
+            serde_json::to_writer_pretty(sidecar, metadata)?;
+            Ok(())
+        }
+    }
+}
+
+pub struct App {
+    should_quit: bool,
+    /// running without the TUI, see `--headless`
+    headless: bool,
+    /// run the self-test before the queued actions, see `--selftest`
+    selftest: bool,
+    /// None with --watchdog-ms 0
+    watchdog_timeout: Option<Duration>,
+    /// see `--saturation-cooldown-ms`
+    saturation_cooldown: Duration,
+    /// when to take the reading again after a saturated one, checked on every
+    /// tick so the loop keeps running in the meantime
+    saturation_retry_at: Option<std::time::Instant>,
+    /// when the session started, for the samples' elapsed time and as the
+    /// watchdog's reference until the dish answers for the first time
+    started_at: std::time::Instant,
+    /// port reopened by the watchdog, only with --reconnect on a real serial port
+    reconnect_port: Option<String>,
+    serial: dish_controller::SerialSettings,
+    record_serial: Option<PathBuf>,
+    command_log: Option<command_log::CommandLog>,
+    last_reconnect: Option<std::time::Instant>,
+    /// when a nudge was last refused at a soft limit, for the flash in the panel
+    limit_flash: Option<std::time::Instant>,
+    /// None when replaying a recording, there's no hardware to talk to
+    dish: Option<Box<dyn DishController>>,
+    replay: Option<PathBuf>,
+    output_path: PathBuf,
+    output_format: OutputFormat,
+    output_file: OutputFile,
+    state: std::sync::Arc<std::sync::RwLock<DishState>>,
+    channel_tx: crossbeam::channel::Sender<GlobalBus>,
+    channel_rx: crossbeam::channel::Receiver<GlobalBus>,
+    /// key presses from the terminal, apart from the library's bus. The
+    /// sender is kept so the channel stays open without a keyboard thread.
+    keys_tx: crossbeam::channel::Sender<KeyEvent>,
+    keys_rx: crossbeam::channel::Receiver<KeyEvent>,
+    /// queues actions for the actions thread, they run one after the other
+    actions_sender: crossbeam::channel::Sender<dish_actions::DishAction>,
+    actions_receiver: crossbeam::channel::Receiver<dish_actions::DishAction>,
+    cancel_action: Arc<AtomicBool>,
+    action_progress: Option<(usize, usize)>,
+    /// most recent rf powers, oldest first
+    power_history: VecDeque<f64>,
+    history_len: usize,
+    heatmap: heatmap::Heatmap,
+    /// show the heatmap instead of the log view
+    show_heatmap: bool,
+    /// show the sky plot instead of the log view
+    show_polar: bool,
+    export_png: Option<PathBuf>,
+    /// line being typed after ':', None when not in command entry mode
+    command_input: Option<String>,
+    /// nudge commands queued per arrow key press
+    nudge_multiplier: u32,
+    /// strongest sample since startup or the last reset
+    best_sample: Option<RfPowerSample>,
+    rotctld_port: Option<u16>,
+    rotctld_bind: std::net::IpAddr,
+    mqtt: Option<mqtt::MqttPublisher>,
+    metrics_port: Option<u16>,
+    database: Option<database::SampleDatabase>,
+    location: Option<dish_actions::GpsLocation>,
+    export_kml: Option<PathBuf>,
+    export_npy: Option<PathBuf>,
+    /// the running scan's grid, only kept with `export_npy`
+    scan_grid: Option<npy::ScanGrid>,
+    /// scans exported so far, every one after the first gets a numbered file
+    /// like the output rotation
+    npy_exports: usize,
+    stow_azimuth: f64,
+    /// seconds of rf readings averaged into each sample
+    rf_watch: u32,
+    motion: dish_actions::MotionOptions,
+    /// nvwrite waiting for Enter to be pressed a second time
+    confirm_command: Option<dish_driver::DishCommand>,
+    ws: Option<ws::WsServer>,
+    influx: Option<influx::InfluxWriter>,
+    /// reset the firmware in the shutdown sequence
+    shutdown_reset: bool,
+    /// written when the output file is created, the firmware version is filled in then
+    metadata: SessionMetadata,
+    /// scan started by the S key, the command line bounds or the last ones
+    /// entered in the scan form
+    scan_template: dish_actions::Scan2DParams,
+    /// scan bounds being entered, None when the form isn't open
+    scan_form: Option<form::Form>,
+    /// position to move to being entered with the G key
+    goto_form: Option<form::Form>,
+    /// positions marked with the M key, Shift+M goes back to the last one
+    marks: Vec<dish_actions::DishPosition>,
+    track_dither: f64,
+    track_period_ms: u64,
+    peak_box: f64,
+    peak_step: f64,
+    beamwidth_step: f64,
+    /// statistics of the running scan, None outside of scans
+    scan_stats: Option<scan_stats::ScanStats>,
+    scan_summary: Option<PathBuf>,
+    /// degrees moved by Shift+arrows
+    move_step: f64,
+    /// most recent lines received from the dish, oldest first
+    raw_lines: VecDeque<String>,
+    /// show the serial panel instead of the log view
+    show_raw_lines: bool,
+    /// lines the serial panel is scrolled up from the newest
+    raw_lines_scroll: usize,
+    log_columns: LogColumns,
+    /// where --satellite-lon should be seen from the dish location
+    satellite_target: Option<dish_actions::DishPosition>,
+    /// dishes beyond the first, dish 1 is at index 0
+    extra_dishes: Vec<ExtraDish>,
+}
+
+fn elevation_calibration(args: &Cli) -> ElevationCalibration {
+    ElevationCalibration {
+        count_min: args.el_count_min,
+        count_max: args.el_count_max,
+        angle_max: args.el_angle_max,
+    }
+}
+
+/// None unless one of the azimuth calibration options was given
+fn azimuth_calibration(args: &Cli) -> Option<AzimuthCalibration> {
+    if args.az_count_offset.is_none() && args.az_counts_per_degree.is_none() {
+        return None;
+    }
+    let default = AzimuthCalibration::default();
+    Some(AzimuthCalibration {
+        count_offset: args.az_count_offset.unwrap_or(default.count_offset),
+        counts_per_degree: args
+            .az_counts_per_degree
+            .unwrap_or(default.counts_per_degree),
+    })
+}
+
+/// The TUI logs to its log view, headless runs to stderr. `--log-file` goes
+/// to the file instead of stderr when headless, and alongside the log view
+/// otherwise.
+fn init_logging(args: &Cli) -> Result<()> {
+    // opened here even for tui_logger, which panics on a path it can't open
+    let log_file = match &args.log_file {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .wrap_err_with(|| format!("could not open log file {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    if args.headless {
+        let mut builder = env_logger::Builder::new();
+        builder.filter_level(args.log_level.unwrap_or(LevelFilter::Info));
+        if let Some(file) = log_file {
+            builder.target(env_logger::Target::Pipe(Box::new(file)));
+        }
+        builder.try_init()?;
+    } else {
+        let level = args.log_level.unwrap_or(LevelFilter::Debug);
+        init_logger(level)?;
+        set_default_level(level);
+        if let Some(path) = &args.log_file {
+            set_log_file(
+                TuiLoggerFile::new(&path.to_string_lossy())
+                    .output_timestamp(Some("%Y-%m-%d %H:%M:%S%.3f".to_string()))
+                    .output_level(Some(tui_logger::TuiLoggerLevelOutput::Long))
+                    .output_separator(' ')
+                    .output_target(true)
+                    .output_file(false)
+                    .output_line(false),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// A port that doesn't open is most often the wrong one, so the error lists
+/// the ports there are
+fn open_error(e: error::DishError) -> color_eyre::Report {
+    match e {
+        error::DishError::PortOpen { .. } => {
+            eyre!("{}; {}", e, dish_controller::available_ports_hint())
+        }
+        e => eyre!(e),
+    }
+}
+
+/// elapsed_s, power, azimuth, elevation of a line of a recorded csv, None
+/// for the header and metadata lines
+fn parse_sample_line(line: &str) -> Option<[f64; 4]> {
+    let fields: Vec<f64> = line
+        .split(',')
+        .take(4)
+        .filter_map(|f| f.trim().parse::<f64>().ok())
+        .collect();
+    fields.try_into().ok()
+}
+
+/// azimuth, elevation and power of the peak of a recorded csv, binned like
+/// the heatmap
+fn load_reference_peak(path: &std::path::Path, resolution: f64) -> Result<(f64, f64, f64)> {
+    let file = File::open(path)
+        .wrap_err_with(|| format!("could not open the reference {}", path.display()))?;
+    let mut heatmap = heatmap::Heatmap::new(resolution);
+    for line in BufReader::new(file).lines() {
+        if let Some([_, power, azimuth, elevation]) = parse_sample_line(&line?) {
+            heatmap.add(azimuth, elevation, power);
+        }
+    }
+    heatmap
+        .peak()
+        .ok_or_else(|| eyre!("no samples in the reference {}", path.display()))
+}
+
+fn append_json_line(path: &std::path::Path, value: &impl Serialize) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    serde_json::to_writer(&mut file, value)?;
+    writeln!(file)
+}
+
+/// state of a dish before it reported anything
+fn initial_state(args: &Cli) -> DishState {
+    DishState {
+        elevation_calibration: elevation_calibration(args),
+        azimuth_calibration: azimuth_calibration(args),
+        rf_smoothing: dish_controller::RfSmoothing::new(args.smoothing),
+        saturation_limit: Some(args.saturation_limit),
+        soft_limits: soft_limits(args),
+        pointing_offset: dish_controller::PointingOffset {
+            azimuth: args.az_offset,
+            elevation: args.el_offset,
+        },
+        ..Default::default()
+    }
+}
+
+fn serial_settings(args: &Cli) -> dish_controller::SerialSettings {
+    dish_controller::SerialSettings {
+        baudrate: args.baudrate,
+        data_bits: args.data_bits,
+        parity: args.parity,
+        stop_bits: args.stop_bits,
+        flow_control: args.flow_control,
+    }
+}
+
+fn rotation(args: &Cli) -> output_file::Rotation {
+    output_file::Rotation {
+        max_age: args
+            .rotate_minutes
+            .map(|minutes| Duration::from_secs(minutes * 60)),
+        max_bytes: args.rotate_mb.map(|mb| mb * 1_000_000),
+    }
+}
+
+fn soft_limits(args: &Cli) -> dish_controller::SoftLimits {
+    dish_controller::SoftLimits {
+        azimuth: (args.az_min, args.az_max),
+        elevation: (args.el_min, args.el_max.unwrap_or(args.el_angle_max)),
+    }
 }
 
-use color_eyre::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
-use ratatui::{DefaultTerminal, Frame};
+/// an elevation given on the command line in degrees, see `--el-unit`
+fn elevation_arg(args: &Cli, value: i32) -> f64 {
+    match args.el_unit {
+        ElevationUnit::Deg => value as f64,
+        ElevationUnit::Count => elevation_calibration(args).count_to_angle(value) + args.el_offset,
+    }
+}
+
+/// the scan described by the command line bounds
+fn scan_params(args: &Cli) -> dish_actions::Scan2DParams {
+    dish_actions::Scan2DParams {
+        bottom_left: dish_actions::DishPosition {
+            azimuth: args.az_start as f64,
+            elevation: elevation_arg(args, args.el_start),
+        },
+        top_right: dish_actions::DishPosition {
+            azimuth: args.az_end as f64,
+            elevation: elevation_arg(args, args.el_end),
+        },
+        step: args.step,
+        dwell_ms: args.dwell_ms,
+        name: None,
+        stop_on_power: args.stop_on_power,
+        samples_per_point: args.samples_per_point,
+    }
+}
+
+fn parse_cli_args() -> Result<(Cli, Vec<dish_actions::DishAction>)> {
+    let matches = Cli::command().get_matches();
+    let mut args = Cli::from_arg_matches(&matches)?;
+
+    if let Some(config_path) = &args.config {
+        config::Config::load(config_path)?.apply(&mut args, &matches);
+    }
+
+    elevation_calibration(&args)
+        .validate()
+        .map_err(|e| eyre!("invalid elevation calibration: {}", e))?;
+
+    let limits = soft_limits(&args);
+    if limits.azimuth.0 >= limits.azimuth.1 || limits.elevation.0 >= limits.elevation.1 {
+        return Err(eyre!("the soft limits are empty: {:?}", limits));
+    }
+
+    if args
+        .az_counts_per_degree
+        .is_some_and(|counts| counts <= 0.0)
+    {
+        return Err(eyre!(
+            "--az-counts-per-degree must be positive, got {:?}",
+            args.az_counts_per_degree
+        ));
+    }
+
+    if !(args.serial_log_rate.is_finite() && args.serial_log_rate > 0.0) {
+        return Err(eyre!(
+            "--serial-log-rate must be a positive number, got {}",
+            args.serial_log_rate
+        ));
+    }
+
+    if !(args.step.is_finite() && args.step > 0.0) {
+        return Err(eyre!("--step must be a positive number, got {}", args.step));
+    }
+
+    if args.beamwidth_step <= 0.0 {
+        return Err(eyre!(
+            "--beamwidth-step must be positive, got {}",
+            args.beamwidth_step
+        ));
+    }
+
+    if !(args.peak_step > 0.0 && args.peak_box >= 0.0) {
+        return Err(eyre!(
+            "--peak-step must be positive and --peak-box not negative, got {} and {}",
+            args.peak_step,
+            args.peak_box
+        ));
+    }
+
+    if !(args.smoothing > 0.0 && args.smoothing <= 1.0) {
+        return Err(eyre!(
+            "--smoothing must be within (0, 1], got {}",
+            args.smoothing
+        ));
+    }
+
+    let mut actions_array = vec![];
+
+    if args.calibrate_elevation {
+        actions_array.push(dish_actions::DishAction::CalibrateElevationRange);
+    }
+
+    if args.scan {
+        let params = scan_params(&args);
+        params
+            .validate(&initial_state(&args))
+            .wrap_err("invalid scan bounds")?;
+
+        actions_array.push(dish_actions::DishAction::Scan2d(params));
+    }
+
+    if let Some(plan_path) = &args.plan {
+        let plan = plan::Plan::load(plan_path)?;
+        info!("Loaded plan with {} targets", plan.targets.len());
+        for action in plan.into_actions(&args) {
+            if let dish_actions::DishAction::Scan2d(params) = &action {
+                params.validate(&initial_state(&args)).wrap_err_with(|| {
+                    format!(
+                        "invalid bounds for {}",
+                        params.name.as_deref().unwrap_or_default()
+                    )
+                })?;
+            }
+            actions_array.push(action);
+        }
+    }
+
+    if let Some(path) = &args.waypoints {
+        let points = plan::load_waypoints(path)?;
+        for (i, point) in points.iter().enumerate() {
+            point
+                .validate(&initial_state(&args))
+                .wrap_err_with(|| format!("invalid waypoint {} in {}", i + 1, path.display()))?;
+        }
+        info!("Loaded {} waypoints", points.len());
+        actions_array.push(dish_actions::DishAction::Waypoints(points));
+    }
+
+    if let Some(start) = args.beamwidth {
+        start
+            .validate(&initial_state(&args))
+            .wrap_err("invalid beamwidth position")?;
+        actions_array.push(dish_actions::DishAction::MeasureBeamwidth {
+            start,
+            step: args.beamwidth_step,
+        });
+    }
+
+    if let Some(position) = args.monitor {
+        position
+            .validate(&initial_state(&args))
+            .wrap_err("invalid monitor position")?;
+        actions_array.push(dish_actions::DishAction::Monitor {
+            position,
+            duration: args
+                .monitor_minutes
+                .map(|minutes| Duration::from_secs(minutes * 60)),
+            interval_ms: args.monitor_interval_ms,
+        });
+    }
+
+    if args.track {
+        actions_array.push(dish_actions::DishAction::StepTrack {
+            dither_deg: args.track_dither,
+            period_ms: args.track_period_ms,
+        });
+    }
+
+    if args.shutdown {
+        actions_array.push(dish_actions::DishAction::Shutdown {
+            stow_azimuth: args.stow_az,
+            reset: args.shutdown_reset,
+        });
+    }
+
+    Ok((args, actions_array))
+}
+
+impl App {
+    fn new(mut args: Cli, actions: Vec<dish_actions::DishAction>) -> Result<Self> {
+        init_logging(&args)?;
+        info!("Starting up...");
+
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let (keys_tx, keys_rx) = crossbeam::channel::unbounded();
+
+        // open before the first `ver` goes out, so that's in it too
+        let mut command_log = match &args.command_log {
+            Some(path) => Some(
+                command_log::CommandLog::open(path, args.command_log_polls)
+                    .wrap_err_with(|| format!("could not open command log {}", path.display()))?,
+            ),
+            None => None,
+        };
+
+        let dish = if let Some(replay) = &args.replay {
+            info!(
+                "Replaying {}, not opening the serial port",
+                replay.display()
+            );
+            None
+        } else {
+            let mut dish: Box<dyn DishController> = if args.simulate {
+                Box::new(simulator::DishSimulator::new(
+                    dish_actions::DishPosition {
+                        azimuth: args.sim_peak_az,
+                        elevation: args.sim_peak_el,
+                    },
+                    elevation_calibration(&args),
+                    tx.clone(),
+                ))
+            } else if let Some(path) = &args.serial_log {
+                Box::new(
+                    dish_controller::SerialLogController::new(
+                        path,
+                        args.serial_log_rate,
+                        tx.clone(),
+                    )
+                    .map_err(|e| eyre!("could not open {}: {}", path.display(), e))?,
+                )
+            } else {
+                if args.port.is_empty() {
+                    let port = dish_controller::detect_port().map_err(|e| eyre!(e))?;
+                    info!("No --port given, using {}", port);
+                    args.port.push(port);
+                }
+                Box::new(
+                    DishSerialController::new(
+                        &args.port[0],
+                        serial_settings(&args),
+                        tx.clone(),
+                        args.record_serial.as_deref(),
+                        0,
+                    )
+                    .map_err(open_error)?,
+                )
+            };
+
+            dish.send_command(dish_driver::DishCommand::Version)
+                .map_err(|e| eyre!("could not talk to the dish: {}", e))?;
+            if let Some(log) = &mut command_log {
+                log.record(0, &dish_driver::DishCommand::Version);
+            }
+
+            std::thread::sleep(Duration::from_millis(1000));
+
+            Some(dish)
+        };
+
+        let location = match (args.latitude, args.longitude) {
+            (Some(latitude), Some(longitude)) => Some(dish_actions::GpsLocation {
+                latitude,
+                longitude,
+            }),
+            _ => None,
+        };
+        let satellite_target = match (location, args.satellite_lon) {
+            (Some(location), Some(satellite_lon)) => {
+                let target = location.geostationary_look_angles(satellite_lon);
+                match target {
+                    Some(target) => info!(
+                        "Satellite at {}° expected at azimuth: {:.2}, elevation: {:.2}",
+                        satellite_lon, target.azimuth, target.elevation
+                    ),
+                    None => warn!("Satellite at {}° is below the horizon", satellite_lon),
+                }
+                target
+            }
+            (None, Some(_)) => {
+                return Err(eyre!(
+                    "--satellite-lon needs the dish location, set --latitude and --longitude"
+                ))
+            }
+            _ => None,
+        };
+
+        let state = std::sync::Arc::new(std::sync::RwLock::new(initial_state(&args)));
+
+        let ws = match args.ws_port {
+            Some(port) => Some(ws::WsServer::start(port, state.clone()).wrap_err_with(|| {
+                format!("could not start the WebSocket server on port {}", port)
+            })?),
+            None => None,
+        };
+
+        let start_time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let output_path = args.output.clone().unwrap_or_else(|| {
+            args.output_dir.join(format!(
+                "rf_power_{}.{}",
+                start_time,
+                args.format.extension()
+            ))
+        });
+
+        let mut extra_dishes = vec![];
+        if args.port.len() > 1 {
+            if dish.is_none() || args.simulate || args.serial_log.is_some() {
+                warn!("Only the first --port is used without real dishes");
+            } else {
+                for (i, port) in args.port.iter().enumerate().skip(1) {
+                    let mut controller = DishSerialController::new(
+                        port,
+                        serial_settings(&args),
+                        tx.clone(),
+                        None,
+                        i,
+                    )
+                    .map_err(open_error)?;
+                    controller
+                        .send_command(dish_driver::DishCommand::Version)
+                        .map_err(|e| eyre!("could not talk to the dish on {}: {}", port, e))?;
+                    if let Some(log) = &mut command_log {
+                        log.record(i, &dish_driver::DishCommand::Version);
+                    }
+                    extra_dishes.push(ExtraDish {
+                        controller: Box::new(controller),
+                        state: initial_state(&args),
+                        output_file: OutputFile::new(
+                            dish_output_path(&output_path, i),
+                            rotation(&args),
+                        ),
+                    });
+                }
+            }
+        }
+
+        let database = match &args.sqlite {
+            Some(path) => {
+                let database = database::SampleDatabase::open(path, &args)
+                    .wrap_err_with(|| format!("could not open database {}", path.display()))?;
+                info!(
+                    "Recording to {} as session {}",
+                    path.display(),
+                    database.session_id()
+                );
+                Some(database)
+            }
+            None => None,
+        };
+
+        let influx = args.influx_url.clone().map(|url| {
+            // samples of the same run share a session tag, matching the sqlite one if recording there too
+            let session = match &database {
+                Some(database) => database.session_id().to_string(),
+                None => start_time.to_string(),
+            };
+            influx::InfluxWriter::start(url, args.influx_token.clone(), session)
+        });
+
+        let metadata = SessionMetadata {
+            started_at: start_time,
+            firmware_version: None,
+            port: match &dish {
+                Some(dish) => dish.port_name().to_string(),
+                None => "replay".to_string(),
+            },
+            baudrate: args.baudrate,
+            scan: args.scan,
+            az_start: args.az_start,
+            az_end: args.az_end,
+            el_start: args.el_start,
+            el_end: args.el_end,
+            el_unit: args.el_unit,
+            step: args.step,
+            dwell_ms: args.dwell_ms,
+            rf_watch: args.rf_watch,
+            plan: args.plan.clone(),
+            latitude: args.latitude,
+            longitude: args.longitude,
+        };
+
+        let scan_template = scan_params(&args);
+
+        let mut heatmap = heatmap::Heatmap::new(args.step);
+        heatmap.set_ramp(args.color_ramp);
+        if let Some(path) = &args.reference {
+            let (azimuth, elevation, power) = load_reference_peak(path, args.step)?;
+            info!(
+                "Reference peak {:.1} @ {:.1}/{:.1} from {}",
+                power,
+                azimuth,
+                elevation,
+                path.display()
+            );
+            heatmap.set_reference(Some((azimuth, elevation)));
+        }
+
+        let (actions_sender, actions_receiver) = crossbeam::channel::unbounded();
+
+        if args.headless && actions.is_empty() && !args.selftest {
+            info!("No actions queued, running until interrupted");
+        }
+        for action in actions {
+            actions_sender.send(action)?;
+        }
+
+        let output_file = OutputFile::new(output_path.clone(), rotation(&args));
+        let reconnect_port =
+            (args.reconnect && dish.is_some() && !args.simulate && args.serial_log.is_none())
+                .then(|| args.port[0].clone());
+
+        Ok(Self {
+            should_quit: false,
+            headless: args.headless,
+            selftest: args.selftest,
+            watchdog_timeout: (args.watchdog_ms > 0)
+                .then(|| Duration::from_millis(args.watchdog_ms)),
+            saturation_cooldown: Duration::from_millis(args.saturation_cooldown_ms),
+            saturation_retry_at: None,
+            started_at: std::time::Instant::now(),
+            reconnect_port,
+            serial: serial_settings(&args),
+            record_serial: args.record_serial.clone(),
+            command_log,
+            last_reconnect: None,
+            limit_flash: None,
+            dish,
+            replay: args.replay,
+            output_file,
+            output_path,
+            extra_dishes,
+            output_format: args.format,
+            state,
+            channel_tx: tx,
+            channel_rx: rx,
+            keys_tx,
+            keys_rx,
+            actions_sender,
+            actions_receiver,
+            cancel_action: Arc::new(AtomicBool::new(false)),
+            action_progress: None,
+            power_history: VecDeque::with_capacity(args.history_len),
+            history_len: args.history_len,
+            heatmap,
+            show_heatmap: false,
+            show_polar: false,
+            raw_lines: VecDeque::new(),
+            show_raw_lines: false,
+            raw_lines_scroll: 0,
+            log_columns: LogColumns::default(),
+            export_png: args.export_png,
+            command_input: None,
+            confirm_command: None,
+            nudge_multiplier: 1,
+            best_sample: None,
+            rotctld_port: args.rotctld_port,
+            rotctld_bind: args.rotctld_bind,
+            metrics_port: args.metrics_port,
+            database,
+            location,
+            satellite_target,
+            export_kml: args.export_kml,
+            export_npy: args.export_npy,
+            scan_grid: None,
+            npy_exports: 0,
+            scan_stats: None,
+            scan_summary: args.scan_summary,
+            stow_azimuth: args.stow_az,
+            metadata,
+            scan_template,
+            track_dither: args.track_dither,
+            move_step: args.move_step,
+            track_period_ms: args.track_period_ms,
+            peak_box: args.peak_box,
+            peak_step: args.peak_step,
+            beamwidth_step: args.beamwidth_step,
+            scan_form: None,
+            goto_form: None,
+            marks: vec![],
+            shutdown_reset: args.shutdown_reset,
+            ws,
+            influx,
+            rf_watch: args.rf_watch,
+            motion: dish_actions::MotionOptions {
+                elevation_maintain: args.elevation_maintain,
+                azimuth_heading: args.azimuth_heading,
+                elevation_counts: args.el_unit == ElevationUnit::Count,
+                tolerance_deg: args.position_tolerance,
+                timeout_ms: args.position_timeout_ms,
+                retries: args.position_retries,
+                rehome: args.rehome,
+            },
+            mqtt: args
+                .mqtt_broker
+                .as_deref()
+                .map(|broker| mqtt::MqttPublisher::connect(broker, args.mqtt_topic.clone())),
+        })
+    }
+
+    /// Runs the application's main loop until the user quits. Without a
+    /// terminal nothing is drawn or read from the keyboard, and the app quits
+    /// once the queued actions are done.
+    pub fn run(&mut self, mut terminal: Option<&mut DefaultTerminal>) -> Result<()> {
+        if terminal.is_some() {
+            self.start_keyboard_thread()?;
+        }
+        // also drives the watchdog
+        self.start_ticker_thread()?;
+        self.start_actions_thread()?;
+        if let Some(replay) = self.replay.clone() {
+            self.start_replay_thread(replay)?;
+        }
+        if let Some(port) = self.rotctld_port {
+            rotctld::RotctldServer::new(
+                self.channel_tx.clone(),
+                self.state.clone(),
+                self.cancel_action.clone(),
+                self.motion,
+            )
+            .start(self.rotctld_bind, port)?;
+        }
+        if let Some(port) = self.metrics_port {
+            metrics::start_server(port, self.state.clone())?;
+        }
+
+        let mut result = Ok(());
+        let mut queue = bus_queue::BusQueue::default();
+        // something changed since the last redraw
+        let mut dirty = false;
+        let mut last_draw = std::time::Instant::now() - MIN_DRAW_INTERVAL;
+        while !self.should_quit {
+            if queue.is_empty() {
+                // wait for the next event or key, but no longer than the
+                // pending redraw is due
+                let redraw = if dirty {
+                    crossbeam::channel::after(MIN_DRAW_INTERVAL.saturating_sub(last_draw.elapsed()))
+                } else {
+                    crossbeam::channel::never()
+                };
+                crossbeam::channel::select! {
+                    recv(self.channel_rx) -> event => match event {
+                        Ok(event) => queue.push(event),
+                        Err(_) => break,
+                    },
+                    // `keys_tx` is ours, so this never disconnects
+                    recv(self.keys_rx) -> key_event => {
+                        if let Ok(key_event) = key_event {
+                            self.handle_key_event(key_event);
+                            dirty = true;
+                        }
+                    }
+                    recv(redraw) -> _ => {}
+                }
+            }
+            // keys go ahead of whatever the bus has queued
+            while let Ok(key_event) = self.keys_rx.try_recv() {
+                self.handle_key_event(key_event);
+                dirty = true;
+            }
+            for event in self.channel_rx.try_iter() {
+                queue.push(event);
+            }
+            if let Some(event) = queue.pop() {
+                trace!("Received: {:?}", event);
+                if let Err(e) = self.handle_bus_event(event) {
+                    error!("{:#}", e);
+                    self.should_quit = true;
+                    result = Err(e);
+                }
+                dirty = true;
+            }
+            if let Some(terminal) = terminal.as_mut() {
+                if dirty && last_draw.elapsed() >= MIN_DRAW_INTERVAL {
+                    terminal.draw(|frame| self.draw(frame))?;
+                    last_draw = std::time::Instant::now();
+                    dirty = false;
+                }
+            } else {
+                dirty = false;
+            }
+        }
+
+        result
+    }
+
+    fn handle_bus_event(&mut self, event: GlobalBus) -> Result<()> {
+        match event {
+            GlobalBus::Update => {
+                self.check_watchdog();
+                self.retry_saturated();
+            }
+
+            GlobalBus::Quit => self.exit(),
+
+            GlobalBus::StopMotion => self.stop_motion(),
+
+            GlobalBus::RawLine(dish, line) => {
+                if self.raw_lines.len() >= RAW_LINES_KEPT {
+                    self.raw_lines.pop_front();
+                }
+                if dish == 0 {
+                    self.raw_lines.push_back(line);
+                } else {
+                    self.raw_lines.push_back(format!("[{}] {}", dish + 1, line));
+                }
+            }
+
+            GlobalBus::UnparsedLine(0, line) => {
+                info!("Unrecognized dish output: {}", line);
+            }
+            GlobalBus::UnparsedLine(dish, line) => {
+                info!("Unrecognized output from dish {}: {}", dish + 1, line);
+            }
+
+            GlobalBus::ActionProgress { done, total } => {
+                self.action_progress = Some((done, total));
+            }
+
+            GlobalBus::ScanStarted(target, grid) => {
+                // bin at the scan's own step, which the scan form can change
+                if let Some(step) = grid.as_ref().and_then(|grid| grid.step()) {
+                    if (step - self.heatmap.resolution()).abs() > 1e-6 * step {
+                        debug!("Heatmap cells now {}°", step);
+                        self.heatmap.set_resolution(step);
+                    }
+                }
+                self.scan_stats = Some(scan_stats::ScanStats::new(target));
+                self.scan_grid = grid.filter(|_| self.export_npy.is_some());
+            }
+
+            GlobalBus::ActionFinished => self.action_progress = None,
+
+            GlobalBus::ScanFinished => {
+                self.action_progress = None;
+                if let Some(summary) = self.scan_stats.take().and_then(|stats| stats.summary()) {
+                    info!("Scan summary: {}", summary);
+                    if let Some((azimuth, elevation)) = self.heatmap.reference() {
+                        info!(
+                            "Peak moved by {:+.1}° azimuth, {:+.1}° elevation from the reference",
+                            (summary.peak_azimuth - azimuth + 180.0).rem_euclid(360.0) - 180.0,
+                            summary.peak_elevation - elevation
+                        );
+                    }
+                    if let Some(path) = &self.scan_summary {
+                        if let Err(e) = append_json_line(path, &summary) {
+                            error!("Could not write the scan summary: {}", e);
+                        }
+                    }
+                }
+                if self.export_png.is_some() {
+                    self.export_heatmap();
+                }
+                if let Some(path) = &self.export_kml {
+                    self.export_kml(path);
+                }
+                if let (Some(path), Some(grid)) = (&self.export_npy, self.scan_grid.take()) {
+                    let path = match self.npy_exports {
+                        0 => path.clone(),
+                        n => output_file::suffixed_path(path, &(n + 1).to_string()),
+                    };
+                    self.npy_exports += 1;
+                    match grid.write(&path) {
+                        Ok(()) => info!("Scan grid exported to {}", path.display()),
+                        Err(e) => error!("Could not export npy to {}: {}", path.display(), e),
+                    }
+                }
+            }
+
+            GlobalBus::DishResponse(dish, response) if dish > 0 => {
+                self.handle_extra_response(dish, response)?;
+            }
+
+            GlobalBus::DishResponse(_, response) => {
+                {
+                    let mut state = self.state.write().unwrap();
+                    if state.no_data {
+                        info!("The dish is answering again");
+                        state.no_data = false;
+                    }
+                    state.update_from_response(&response);
+                }
+
+                match &response {
+                    DishResponse::NvValue(addr, value) => info!("NVRAM[{}] = {}", addr, value),
+                    DishResponse::Error(line) => warn!("Dish error: {}", line),
+                    DishResponse::ScanState(true) => info!("Firmware scan running"),
+                    DishResponse::ScanState(false) => info!("Firmware scan stopped"),
+                    _ => {}
+                }
+
+                if let DishResponse::RfPower(pow) = response {
+                    let rf_power_sample = {
+                        let mut state = self.state.write().unwrap();
+                        let (power, power_stddev) = match &mut state.point_readings {
+                            Some(point) => match point.add(pow) {
+                                Some((mean, stddev)) => (mean, Some(stddev)),
+                                // more readings to come for this point
+                                None => return Ok(()),
+                            },
+                            None => (pow, None),
+                        };
+                        RfPowerSample {
+                            power,
+                            azimuth: state.azimuth_angle,
+                            elevation: state.elevation_angle,
+                            elapsed: self.started_at.elapsed(),
+                            timestamp: std::time::SystemTime::now(),
+                            target: state.target.clone(),
+                            power_stddev,
+                        }
+                    };
+                    self.channel_tx
+                        .send(GlobalBus::RfPowerSample(rf_power_sample))?;
+                }
+            }
+
+            GlobalBus::DishCommand(command) => {
+                self.send_command(command);
+            }
+
+            GlobalBus::PollCommand(dish, command) => {
+                if let Err(e) = self.send_to(dish, command) {
+                    error!("Could not poll dish {}: {}", dish + 1, e);
+                }
+            }
+
+            GlobalBus::RfPowerSample(power) => {
+                info!(
+                    "Power: {}, Azimuth: {:.4}, Elevation: {:.4}",
+                    power.power, power.azimuth, power.elevation
+                );
+
+                if self.state.read().unwrap().is_saturating(power.power) {
+                    warn!(
+                        "Power {} is over the saturation limit, retrying in {} ms",
+                        power.power,
+                        self.saturation_cooldown.as_millis()
+                    );
+                    self.saturation_retry_at
+                        .get_or_insert(std::time::Instant::now() + self.saturation_cooldown);
+                    return Ok(());
+                }
+
+                if self.power_history.len() >= self.history_len {
+                    self.power_history.pop_front();
+                }
+                self.power_history.push_back(power.power);
+
+                if self
+                    .best_sample
+                    .as_ref()
+                    .is_none_or(|best| power.power > best.power)
+                {
+                    self.best_sample = Some(power.clone());
+                }
+
+                if let Some(mqtt) = &self.mqtt {
+                    mqtt.publish(&power);
+                }
+                if let Some(ws) = &self.ws {
+                    ws.publish(&power);
+                }
+                self.heatmap
+                    .add(power.azimuth, power.elevation, power.power);
+                if let Some(stats) = &mut self.scan_stats {
+                    stats.add(&power);
+                }
+                if let Some(grid) = &mut self.scan_grid {
+                    grid.add(power.azimuth, power.elevation, power.power);
+                }
+
+                if self.replay.is_some() {
+                    // don't record the recording again
+                    return Ok(());
+                }
+
+                self.record_sample(&power)?;
+
+                if let Some(database) = &self.database {
+                    if let Err(e) = database.insert(&power) {
+                        error!("Could not record sample to the database: {}", e);
+                    }
+                }
+                if let Some(influx) = &self.influx {
+                    influx.publish(&power);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the file samples are recorded to, opening it (and writing the
+    /// header into every new part) on first use
+    fn output_file(&mut self) -> io::Result<&mut File> {
+        let format = self.output_format;
+        let metadata = &mut self.metadata;
+        let state = &self.state;
+        self.output_file.get(|file, path| {
+            metadata.firmware_version = state.read().unwrap().firmware_version.clone();
+            write_metadata(metadata, format, file, path)?;
+            if format == OutputFormat::Csv {
+                writeln!(file, "{}", CSV_HEADER)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn record_sample(&mut self, sample: &RfPowerSample) -> io::Result<()> {
+        let format = self.output_format;
+        write_sample(self.output_file()?, format, sample)
+    }
+
+    /// keeps the state of a dish other than the first up to date and records
+    /// its readings
+    fn handle_extra_response(&mut self, dish: usize, response: DishResponse) -> io::Result<()> {
+        let format = self.output_format;
+        let target = self.state.read().unwrap().target.clone();
+        let Some(extra) = self.extra_dishes.get_mut(dish - 1) else {
+            return Ok(());
+        };
+        if extra.state.no_data {
+            info!("Dish {} is answering again", dish + 1);
+            extra.state.no_data = false;
+        }
+        extra.state.update_from_response(&response);
+
+        match response {
+            DishResponse::Error(line) => warn!("Dish {} error: {}", dish + 1, line),
+            DishResponse::RfPower(power) => {
+                let sample = RfPowerSample {
+                    power,
+                    azimuth: extra.state.azimuth_angle,
+                    elevation: extra.state.elevation_angle,
+                    elapsed: self.started_at.elapsed(),
+                    timestamp: std::time::SystemTime::now(),
+                    target,
+                    power_stddev: None,
+                };
+                info!(
+                    "Dish {} power: {}, Azimuth: {:.4}, Elevation: {:.4}",
+                    dish + 1,
+                    sample.power,
+                    sample.azimuth,
+                    sample.elevation
+                );
+                write_sample(extra.output_file(format)?, format, &sample)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        frame.render_widget(self, frame.area());
+    }
+
+    fn start_keyboard_thread(&mut self) -> io::Result<()> {
+        let sender_clone = self.keys_tx.clone();
+        std::thread::spawn(move || {
+            loop {
+                let event = match event::read() {
+                    Ok(event) => event,
+                    Err(e) => {
+                        error!("Could not read terminal events: {}", e);
+                        break;
+                    }
+                };
+                // it's important to check that the event is a key press event as
+                // crossterm also emits key release and repeat events on Windows.
+                let Event::Key(key_event) = event else {
+                    continue;
+                };
+                if key_event.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if sender_clone.send(key_event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(())
+    }
 
-#[derive(Debug)]
-pub struct RfPowerSample {
-    pub power: f64,
-    pub azimuth: f64,
-    pub elevation: f64,
-    pub time: std::time::Instant,
-}
+    /// wakes the main loop up regularly, so the time based widgets keep
+    /// refreshing while nothing else happens on the bus
+    fn start_ticker_thread(&mut self) -> io::Result<()> {
+        let sender_clone = self.channel_tx.clone();
+        std::thread::spawn(move || {
+            while sender_clone.send(GlobalBus::Update).is_ok() {
+                std::thread::sleep(TICK_INTERVAL);
+            }
+        });
 
-#[derive(Debug)]
-pub enum GlobalBus {
-    KeyboardEvent(KeyEvent),
-    DishCommand(dish_driver::DishCommand),
-    DishResponse(DishResponse),
-    RfPowerSample(RfPowerSample),
-    Update,
-}
+        Ok(())
+    }
 
-pub struct App {
-    should_quit: bool,
-    dish: DishSerialController,
-    state: std::sync::Arc<std::sync::RwLock<DishState>>,
-    channel_tx: crossbeam::channel::Sender<GlobalBus>,
-    channel_rx: crossbeam::channel::Receiver<GlobalBus>,
-    //actions_list: Vec<dish_actions::DishAction>,
-    _actions_sender: crossbeam::channel::Sender<dish_actions::DishAction>,
-    actions_receiver: crossbeam::channel::Receiver<dish_actions::DishAction>,
-}
+    /// feeds the samples of a recorded csv back through the bus, following
+    /// the recorded timeline
+    fn start_replay_thread(&mut self, path: PathBuf) -> io::Result<()> {
+        let file = std::fs::File::open(&path)?;
+        let sender_clone = self.channel_tx.clone();
+        let state = self.state.clone();
 
-fn parse_cli_args() -> Result<(Cli, Vec<dish_actions::DishAction>)> {
-    let args = Cli::parse();
+        std::thread::spawn(move || {
+            let mut last_time = None;
+            for line in BufReader::new(file).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let Some([time, power, azimuth, elevation]) = parse_sample_line(&line) else {
+                    debug!("Skipping replay line: {}", line);
+                    continue;
+                };
 
-    let mut actions_array = vec![];
+                if let Some(last_time) = last_time {
+                    let delta: f64 = time - last_time;
+                    std::thread::sleep(Duration::from_secs_f64(delta.max(0.0)));
+                }
+                last_time = Some(time);
 
-    if args.scan {
-        actions_array.push(dish_actions::DishAction::Scan2d(
-            dish_actions::Scan2DParams {
-                bottom_left: dish_actions::DishPosition {
-                    azimuth: args.az_start as f64,
-                    elevation: args.el_start as f64,
-                },
-                top_right: dish_actions::DishPosition {
-                    azimuth: args.az_end as f64,
-                    elevation: args.el_end as f64,
-                },
-                step: args.step,
-            },
-        ));
+                {
+                    let mut state = state.write().unwrap();
+                    state.azimuth_angle = azimuth;
+                    state.elevation_angle = elevation;
+                    state.update_from_response(&DishResponse::RfPower(power));
+                }
+
+                let sample = RfPowerSample {
+                    power,
+                    azimuth,
+                    elevation,
+                    // the recorded timeline, so a replay recorded again matches
+                    elapsed: Duration::from_secs_f64(time.max(0.0)),
+                    timestamp: std::time::SystemTime::now(),
+                    target: None,
+                    power_stddev: None,
+                };
+                if sender_clone.send(GlobalBus::RfPowerSample(sample)).is_err() {
+                    break;
+                }
+            }
+            info!("Replay of {} finished", path.display());
+        });
+
+        Ok(())
     }
 
-    Ok((args, actions_array))
-}
+    fn start_actions_thread(&mut self) -> io::Result<()> {
+        let recv_clone = self.actions_receiver.clone();
+        let sender = self.channel_tx.clone();
+        let headless = self.headless;
+        let selftest = self.selftest;
 
-impl App {
-    fn new(args: Cli, actions: Vec<dish_actions::DishAction>) -> Result<Self> {
-        init_logger(LevelFilter::Debug)?;
-        set_default_level(LevelFilter::Debug);
-        info!("Starting up...");
+        let actions = dish_actions::ActionManager::new(
+            self.channel_tx.clone(),
+            self.state.clone(),
+            self.cancel_action.clone(),
+            self.rf_watch,
+            self.motion,
+        );
 
-        let (tx, rx) = crossbeam::channel::unbounded();
+        std::thread::spawn(move || {
+            if selftest {
+                if !actions.self_test() {
+                    let dropped = recv_clone.try_iter().count();
+                    if dropped > 0 {
+                        error!("Dropping the {} queued actions", dropped);
+                    }
+                }
+                if headless && recv_clone.is_empty() {
+                    let _ = sender.send(GlobalBus::Quit);
+                }
+            }
 
-        let mut dish = DishSerialController::new(&args.port, args.baudrate, tx.clone()).unwrap();
+            loop {
+                if let Ok(action) = recv_clone.recv() {
+                    info!("Executing action: {:#?}", action);
+                    actions.render(action);
+                    let _ = sender.send(GlobalBus::ActionFinished);
+                    // nobody is there to queue more
+                    if headless && recv_clone.is_empty() {
+                        info!("All actions done, quitting");
+                        let _ = sender.send(GlobalBus::Quit);
+                    }
+                }
+            }
+        });
 
-        dish.send_command(dish_driver::DishCommand::Version)
-            .unwrap();
+        Ok(())
+    }
 
-        std::thread::sleep(Duration::from_millis(1000));
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if self.command_input.is_some() {
+            self.handle_command_input(key_event);
+            return;
+        }
+        if self.scan_form.is_some() {
+            self.handle_scan_form(key_event);
+            return;
+        }
+        if self.goto_form.is_some() {
+            self.handle_goto_form(key_event);
+            return;
+        }
 
-        let state = DishState {
-            azimuth_count: 0,
-            azimuth_angle: 0.0,
-            elevation_count: 0,
-            elevation_angle: 0.0,
-            signal_strength: 0.0,
-        };
+        match key_event.code {
+            KeyCode::Char('q') => self.exit(),
+            KeyCode::Char(':') => self.command_input = Some(String::new()),
+            KeyCode::Char('h') => {
+                self.show_heatmap = !self.show_heatmap;
+                self.show_polar = false;
+            }
+            KeyCode::Char('o') => {
+                self.show_polar = !self.show_polar;
+                self.show_heatmap = false;
+            }
+            KeyCode::Char('w') => {
+                self.show_raw_lines = !self.show_raw_lines;
+                self.raw_lines_scroll = 0;
+            }
+            KeyCode::Char('1') => self.log_columns.target = !self.log_columns.target,
+            KeyCode::Char('2') => self.log_columns.file = !self.log_columns.file,
+            KeyCode::Char('3') => self.log_columns.line = !self.log_columns.line,
+            KeyCode::Char('4') => self.log_columns.timestamp = self.log_columns.timestamp.next(),
+            KeyCode::PageUp if self.show_raw_lines => {
+                self.raw_lines_scroll =
+                    (self.raw_lines_scroll + 10).min(self.raw_lines.len().saturating_sub(1));
+            }
+            KeyCode::PageDown if self.show_raw_lines => {
+                self.raw_lines_scroll = self.raw_lines_scroll.saturating_sub(10);
+            }
+            KeyCode::Char('r') => {
+                info!("Peak reset");
+                self.best_sample = None;
+            }
+            KeyCode::Char('e') => self.export_heatmap(),
+            KeyCode::Char('s') => self.queue_scan(self.scan_template.clone()),
+            KeyCode::Char('S') => {
+                let template = &self.scan_template;
+                self.scan_form = Some(form::Form::new(
+                    "Scan",
+                    &[
+                        ("Az from", template.bottom_left.azimuth),
+                        ("to", template.top_right.azimuth),
+                        ("El from", template.bottom_left.elevation),
+                        ("to", template.top_right.elevation),
+                        ("Step", template.step),
+                    ],
+                ));
+            }
+            KeyCode::Char('g') => {
+                let (azimuth, elevation) = {
+                    let state = self.state.read().unwrap();
+                    (state.azimuth_angle, state.elevation_angle)
+                };
+                self.goto_form = Some(form::Form::new(
+                    "Go to",
+                    &[
+                        ("Az", (azimuth * 10.0).round() / 10.0),
+                        ("El", (elevation * 10.0).round() / 10.0),
+                    ],
+                ));
+            }
+            KeyCode::Char('m') => {
+                let position = {
+                    let state = self.state.read().unwrap();
+                    dish_actions::DishPosition {
+                        azimuth: state.azimuth_angle,
+                        elevation: state.elevation_angle,
+                    }
+                };
+                self.marks.push(position);
+                info!(
+                    "Marked {:.2}/{:.2}, {} marks",
+                    position.azimuth,
+                    position.elevation,
+                    self.marks.len()
+                );
+            }
+            KeyCode::Char('M') => match self.marks.pop() {
+                Some(position) => {
+                    info!(
+                        "Going back to the mark at {:.2}/{:.2}",
+                        position.azimuth, position.elevation
+                    );
+                    self.queue_action(dish_actions::DishAction::MoveAngles(
+                        position.azimuth,
+                        position.elevation,
+                    ));
+                }
+                None => warn!("No marked position to go back to"),
+            },
+            KeyCode::Char('p') => self.queue_action(dish_actions::DishAction::Stow {
+                azimuth: self.stow_azimuth,
+            }),
+            KeyCode::Char('t') => self.queue_action(dish_actions::DishAction::StepTrack {
+                dither_deg: self.track_dither,
+                period_ms: self.track_period_ms,
+            }),
+            KeyCode::Char('c') => self.queue_action(dish_actions::DishAction::LocalPeak {
+                box_deg: self.peak_box,
+                step: self.peak_step,
+            }),
+            KeyCode::Char('b') => {
+                let start = {
+                    let state = self.state.read().unwrap();
+                    dish_actions::DishPosition {
+                        azimuth: state.azimuth_angle,
+                        elevation: state.elevation_angle,
+                    }
+                };
+                self.queue_action(dish_actions::DishAction::MeasureBeamwidth {
+                    start,
+                    step: self.beamwidth_step,
+                })
+            }
+            KeyCode::Char('Q') => self.queue_action(dish_actions::DishAction::Shutdown {
+                stow_azimuth: self.stow_azimuth,
+                reset: self.shutdown_reset,
+            }),
+            KeyCode::Esc => {
+                warn!("Cancelling current action");
+                self.cancel_action.store(true, Ordering::Relaxed);
+                // or the last move of the action would still run to the end
+                self.stop_motion();
+            }
+            KeyCode::Char('x') => self.stop_motion(),
+            KeyCode::Char('l') => {
+                if self.state.read().unwrap().hold.is_some() {
+                    self.cancel_action.store(true, Ordering::Relaxed);
+                } else {
+                    self.queue_action(dish_actions::DishAction::Hold);
+                }
+            }
+            KeyCode::Char('f') => {
+                let command = if self.state.read().unwrap().firmware_scan {
+                    dish_driver::DishCommand::ScanStop
+                } else {
+                    dish_driver::DishCommand::ScanStart
+                };
+                self.send_command(command);
+            }
+            KeyCode::Char('+') => self.nudge_multiplier = (self.nudge_multiplier + 1).min(50),
+            KeyCode::Char('-') => self.nudge_multiplier = (self.nudge_multiplier - 1).max(1),
+            KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down
+                if key_event.modifiers.contains(KeyModifiers::SHIFT) =>
+            {
+                let step = self.move_step;
+                let (d_az, d_el) = match key_event.code {
+                    KeyCode::Left => (-step, 0.0),
+                    KeyCode::Right => (step, 0.0),
+                    KeyCode::Up => (0.0, step),
+                    _ => (0.0, -step),
+                };
+                self.queue_action(dish_actions::DishAction::MoveRelative { d_az, d_el });
+            }
+            KeyCode::Left => self.nudge(dish_driver::DishCommand::NudgeAzimuthCcw),
+            KeyCode::Right => self.nudge(dish_driver::DishCommand::NudgeAzimuthCw),
+            KeyCode::Up => self.nudge(dish_driver::DishCommand::NudgeElevationUp),
+            KeyCode::Down => self.nudge(dish_driver::DishCommand::NudgeElevationDown),
+            KeyCode::Char(' ') => {
+                self.send_command(dish_driver::DishCommand::RfWatch(self.rf_watch))
+            }
 
-        let state = std::sync::Arc::new(std::sync::RwLock::new(state));
+            _ => {}
+        }
+    }
 
-        let (actions_sender, actions_receiver) = crossbeam::channel::unbounded();
+    /// queues `nudge_multiplier` copies of a nudge command, or as many of them
+    /// as fit before the soft limit in that direction
+    fn nudge(&mut self, command: dish_driver::DishCommand) {
+        let allowed = {
+            let state = self.state.read().unwrap();
+            state.soft_limits.nudges_allowed(
+                &command,
+                state.dish_azimuth(state.azimuth_angle),
+                state.dish_elevation(state.elevation_angle),
+                self.nudge_multiplier,
+            )
+        };
+        if allowed < self.nudge_multiplier {
+            warn!(
+                "Nudging {} of {} times, the rest would pass the soft limit: {:?}",
+                allowed, self.nudge_multiplier, command
+            );
+            self.limit_flash = Some(std::time::Instant::now());
+        }
 
-        for action in actions {
-            actions_sender.send(action).unwrap();
+        for _ in 0..allowed {
+            if let Err(e) = self
+                .channel_tx
+                .send(GlobalBus::DishCommand(command.clone()))
+            {
+                error!("Could not queue {:?}: {}", command, e);
+                self.should_quit = true;
+                return;
+            }
         }
+    }
 
-        Ok(Self {
-            should_quit: false,
-            dish,
-            state,
-            channel_tx: tx,
-            channel_rx: rx,
-            //actions_list,
-            _actions_sender: actions_sender,
-            actions_receiver,
-        })
+    fn queue_scan(&mut self, params: dish_actions::Scan2DParams) {
+        let validated = params.validate(&self.state.read().unwrap());
+        if let Err(e) = validated {
+            error!("Not starting the scan: {}", e);
+            return;
+        }
+
+        self.queue_action(dish_actions::DishAction::Scan2d(params));
     }
 
-    /// runs the application's main loop until the user quits
-    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
-        self.start_keyboard_thread()?;
-        self.start_actions_thread()?;
+    /// hands the action to the actions thread, it starts once the running one
+    /// is done (Esc cancels that)
+    fn queue_action(&self, action: dish_actions::DishAction) {
+        info!("Queued {:?}", action);
+        if let Err(e) = self.actions_sender.send(action) {
+            error!("Could not queue the action: {}", e);
+        }
+    }
 
-        let start_time_string = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            .to_string();
+    /// edits the scan bounds, queueing the scan on Enter
+    fn handle_scan_form(&mut self, key_event: KeyEvent) {
+        let Some(scan_form) = &mut self.scan_form else {
+            return;
+        };
 
-        while !self.should_quit {
-            let recv = self.channel_rx.recv();
-            trace!("Received: {:?}", recv);
-            match recv {
-                Ok(GlobalBus::KeyboardEvent(key_event)) => {
-                    self.handle_key_event(key_event);
-                }
-                Ok(GlobalBus::Update) => {}
-
-                Ok(GlobalBus::DishResponse(response)) => {
-                    self.state.write().unwrap().update_from_response(&response);
-
-                    if let DishResponse::RfPower(pow) = response {
-                        let rf_power_sample = RfPowerSample {
-                            power: pow,
-                            azimuth: self.state.read().unwrap().azimuth_angle,
-                            elevation: self.state.read().unwrap().elevation_angle,
-                            time: std::time::Instant::now(),
-                        };
-                        self.channel_tx
-                            .send(GlobalBus::RfPowerSample(rf_power_sample))
-                            .unwrap();
+        match scan_form.handle_key(key_event) {
+            form::FormEvent::Pending => {}
+            form::FormEvent::Cancel => self.scan_form = None,
+            form::FormEvent::Submit => match scan_form.values().as_deref() {
+                Ok(&[az_start, az_end, el_start, el_end, step]) => {
+                    self.scan_form = None;
+                    self.scan_template.bottom_left = dish_actions::DishPosition {
+                        azimuth: az_start,
+                        elevation: el_start,
+                    };
+                    self.scan_template.top_right = dish_actions::DishPosition {
+                        azimuth: az_end,
+                        elevation: el_end,
+                    };
+                    self.scan_template.step = step;
+                    self.queue_scan(self.scan_template.clone());
+                }
+                Ok(_) => {}
+                Err(label) => scan_form.set_error(format!("{} is not a number", label)),
+            },
+        }
+    }
+
+    /// edits the position to go to, moving there on Enter if it's reachable
+    fn handle_goto_form(&mut self, key_event: KeyEvent) {
+        let Some(goto_form) = &mut self.goto_form else {
+            return;
+        };
+
+        match goto_form.handle_key(key_event) {
+            form::FormEvent::Pending => {}
+            form::FormEvent::Cancel => self.goto_form = None,
+            form::FormEvent::Submit => match goto_form.values().as_deref() {
+                Ok(&[azimuth, elevation]) => {
+                    let position = dish_actions::DishPosition { azimuth, elevation };
+                    let validated = position.validate(&self.state.read().unwrap());
+                    if let Err(e) = validated {
+                        goto_form.set_error(e.to_string());
+                        return;
                     }
+                    self.goto_form = None;
+                    info!(
+                        "Going to azimuth: {:.2}, elevation: {:.2}",
+                        azimuth, elevation
+                    );
+                    self.queue_action(dish_actions::DishAction::MoveAngles(azimuth, elevation));
                 }
+                Ok(_) => {}
+                Err(label) => goto_form.set_error(format!("{} is not a number", label)),
+            },
+        }
+    }
+
+    /// edits the raw command line, sending it on Enter
+    fn handle_command_input(&mut self, key_event: KeyEvent) {
+        let Some(input) = &mut self.command_input else {
+            return;
+        };
 
-                Ok(GlobalBus::DishCommand(command)) => {
-                    self.dish.send_command(command).unwrap();
+        match key_event.code {
+            KeyCode::Char(c) => {
+                input.push(c);
+                self.confirm_command = None;
+            }
+            KeyCode::Backspace => {
+                input.pop();
+                self.confirm_command = None;
+            }
+            KeyCode::Esc => {
+                self.command_input = None;
+                self.confirm_command = None;
+            }
+            KeyCode::Enter => {
+                let line = input.trim().to_string();
+                if line.is_empty() {
+                    self.command_input = None;
+                    return;
                 }
 
-                Ok(GlobalBus::RfPowerSample(power)) => {
-                    info!(
-                        "Power: {}, Azimuth: {:.4}, Elevation: {:.4}",
-                        power.power, power.azimuth, power.elevation
+                // nvram commands are recognized so writes go through the
+                // confirmation, the rest goes out raw so the reply is echoed
+                let is_nv_write = line
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|word| word.eq_ignore_ascii_case("nvwrite"));
+                let command = if is_nv_write {
+                    // sent raw, a malformed write could still reach the nvram
+                    match line.to_lowercase().parse() {
+                        Ok(command) => command,
+                        Err(e) => {
+                            error!("Not sending {:?}: {}", line, e);
+                            return;
+                        }
+                    }
+                } else {
+                    match line.parse() {
+                        Ok(command @ dish_driver::DishCommand::NvRead(_)) => command,
+                        _ => dish_driver::DishCommand::Raw(line),
+                    }
+                };
+                if matches!(command, dish_driver::DishCommand::NvWrite { .. })
+                    && self.confirm_command.as_ref() != Some(&command)
+                {
+                    warn!(
+                        "{:?} changes the persistent settings, press Enter again to send it",
+                        command
                     );
+                    self.confirm_command = Some(command);
+                    return;
+                }
 
-                    if power.power > 5000.0 {
-                        warn!("what the hell? power is too high");
+                self.command_input = None;
+                self.confirm_command = None;
+                self.send_command(command);
+            }
+            _ => {}
+        }
+    }
 
-                        self.dish
-                            .send_command(dish_driver::DishCommand::RfWatch(1))
-                            .unwrap();
+    /// saves the heatmap to the --export-png file, or next to the csv output
+    fn export_heatmap(&self) {
+        let path = self
+            .export_png
+            .clone()
+            .unwrap_or_else(|| self.output_path.with_extension("png"));
 
-                        std::thread::sleep(Duration::from_secs(1));
+        match self.heatmap.export_png(&path) {
+            Ok(()) => info!("Heatmap exported to {}", path.display()),
+            Err(e) => error!("Could not export heatmap to {}: {}", path.display(), e),
+        }
+    }
 
-                        continue;
-                    }
+    fn export_kml(&self, path: &std::path::Path) {
+        let Some(location) = self.location else {
+            error!("Can't export kml without the dish location, set --latitude and --longitude");
+            return;
+        };
 
-                    let mut file = OpenOptions::new()
-                        .create(true)
-                        .append(true)
-                        .open(format!("rf_power_{}.csv", start_time_string))
-                        .unwrap();
+        match kml::write_kml(path, location, self.heatmap.cells()) {
+            Ok(()) => info!("Scan exported to {}", path.display()),
+            Err(e) => error!("Could not export kml to {}: {}", path.display(), e),
+        }
+    }
 
-                    writeln!(
-                        file,
-                        "{},{},{},{}",
-                        power.time.elapsed().as_secs(),
-                        power.power,
-                        power.azimuth,
-                        power.elevation
-                    )
-                    .unwrap();
-                }
+    fn send_command(&mut self, command: dish_driver::DishCommand) {
+        if let Err(e) = command.validate() {
+            error!("Not sending {:?}: {}", command, e);
+            return;
+        }
 
-                Err(_) => {}
+        for i in 0..self.extra_dishes.len() {
+            let extra = &self.extra_dishes[i];
+            let command = extra
+                .state
+                .soft_limits
+                .clamp(command.clone(), &extra.state.elevation_calibration);
+            if let Err(e) = self.send_to(i + 1, command) {
+                error!("Could not send command to dish {}: {}", i + 2, e);
             }
-            terminal.draw(|frame| self.draw(frame))?;
         }
 
-        Ok(())
+        let command = {
+            let state = self.state.read().unwrap();
+            state
+                .soft_limits
+                .clamp(command, &state.elevation_calibration)
+        };
+
+        if let Err(e) = self.send_to(0, command) {
+            error!("Could not send command to the dish: {}", e);
+        }
     }
 
-    fn draw(&self, frame: &mut Frame) {
-        frame.render_widget(self, frame.area());
+    /// Sends the command to the dish with the given index as it is, noting it
+    /// in the command log once sent
+    fn send_to(
+        &mut self,
+        dish: usize,
+        command: dish_driver::DishCommand,
+    ) -> Result<(), error::DishError> {
+        let controller = match dish {
+            0 => self.dish.as_mut(),
+            _ => self
+                .extra_dishes
+                .get_mut(dish - 1)
+                .map(|extra| &mut extra.controller),
+        };
+        let Some(controller) = controller else {
+            trace!("No dish connected, dropping {:?}", command);
+            return Ok(());
+        };
+
+        controller.send_command(command.clone())?;
+        if let Some(log) = &mut self.command_log {
+            log.record(dish, &command);
+        }
+        Ok(())
     }
 
-    fn start_keyboard_thread(&mut self) -> io::Result<()> {
-        let sender_clone = self.channel_tx.clone();
-        std::thread::spawn(move || {
-            loop {
-                match event::read().unwrap() {
-                    // it's important to check that the event is a key press event as
-                    // crossterm also emits key release and repeat events on Windows.
-                    Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                        sender_clone
-                            .send(GlobalBus::KeyboardEvent(key_event))
-                            .unwrap();
-                    }
-                    _ => {}
-                };
+    /// Stops every dish at its current readback
+    fn stop_motion(&mut self) {
+        warn!("Stopping the motors");
+        for i in 0..self.extra_dishes.len() {
+            for command in self.extra_dishes[i].state.stop_commands() {
+                if let Err(e) = self.send_to(i + 1, command) {
+                    error!("Could not stop dish {}: {}", i + 2, e);
+                }
             }
-        });
+        }
 
-        Ok(())
+        let commands = self.state.read().unwrap().stop_commands();
+        for command in commands {
+            if let Err(e) = self.send_to(0, command) {
+                error!("Could not stop the dish: {}", e);
+            }
+        }
     }
 
-    fn start_actions_thread(&mut self) -> io::Result<()> {
-        let recv_clone = self.actions_receiver.clone();
+    fn exit(&mut self) {
+        self.should_quit = true;
+    }
 
-        let actions = dish_actions::ActionManager::new(self.channel_tx.clone(), self.state.clone());
+    /// Takes the reading again once the cooldown after a saturated one is
+    /// over.
+    fn retry_saturated(&mut self) {
+        if self
+            .saturation_retry_at
+            .is_some_and(|at| std::time::Instant::now() >= at)
+        {
+            self.saturation_retry_at = None;
+            self.send_command(dish_driver::DishCommand::RfWatch(self.rf_watch));
+        }
+    }
+
+    /// Flags the link as down when a dish went quiet for longer than
+    /// `watchdog_timeout`, and with `--reconnect` reopens the port of the
+    /// first one until it answers again.
+    fn check_watchdog(&mut self) {
+        let Some(timeout) = self.watchdog_timeout else {
+            return;
+        };
+        if self.dish.is_none() {
+            return;
+        }
 
-        std::thread::spawn(move || loop {
-            if let Ok(action) = recv_clone.recv() {
-                info!("Executing action: {:#?}", action);
-                actions.render(action);
+        for (i, extra) in self.extra_dishes.iter_mut().enumerate() {
+            let silent_for = extra
+                .state
+                .last_response
+                .unwrap_or(self.started_at)
+                .elapsed();
+            if silent_for >= timeout && !extra.state.no_data {
+                error!(
+                    "No response from dish {} for {:.1} s, the link may be down",
+                    i + 2,
+                    silent_for.as_secs_f64()
+                );
+                extra.state.no_data = true;
             }
-        });
+        }
 
-        Ok(())
-    }
+        let silent_for = {
+            let mut state = self.state.write().unwrap();
+            let silent_for = state.last_response.unwrap_or(self.started_at).elapsed();
+            if silent_for < timeout {
+                return;
+            }
+            if !state.no_data {
+                error!(
+                    "No response from the dish for {:.1} s, the link may be down",
+                    silent_for.as_secs_f64()
+                );
+                state.no_data = true;
+            }
+            silent_for
+        };
 
-    fn handle_key_event(&mut self, key_event: KeyEvent) {
-        match key_event.code {
-            KeyCode::Char('q') => self.exit(),
-            KeyCode::Left => self
-                .dish
-                .send_command(dish_driver::DishCommand::NudgeAzimuthCcw)
-                .unwrap(),
-            KeyCode::Right => self
-                .dish
-                .send_command(dish_driver::DishCommand::NudgeAzimuthCw)
-                .unwrap(),
-            KeyCode::Up => self
-                .dish
-                .send_command(dish_driver::DishCommand::NudgeElevationUp)
-                .unwrap(),
-            KeyCode::Down => self
-                .dish
-                .send_command(dish_driver::DishCommand::NudgeElevationDown)
-                .unwrap(),
-            KeyCode::Char(' ') => self
-                .dish
-                .send_command(dish_driver::DishCommand::RfWatch(1))
-                .unwrap(),
+        let Some(port) = self.reconnect_port.clone() else {
+            return;
+        };
+        if self
+            .last_reconnect
+            .is_some_and(|last| last.elapsed() < RECONNECT_INTERVAL)
+        {
+            return;
+        }
+        self.last_reconnect = Some(std::time::Instant::now());
 
-            _ => {}
+        info!(
+            "Reconnecting to {}, silent for {:.0} s",
+            port,
+            silent_for.as_secs_f64()
+        );
+        // the old controller has to let go of the port before it's reopened
+        self.dish = None;
+        match DishSerialController::new(
+            &port,
+            self.serial,
+            self.channel_tx.clone(),
+            self.record_serial.as_deref(),
+            0,
+        ) {
+            Ok(controller) => {
+                self.dish = Some(Box::new(controller));
+                if let Err(e) = self.send_to(0, dish_driver::DishCommand::Version) {
+                    warn!("Reconnected to {}, but could not talk to it: {}", port, e);
+                }
+            }
+            Err(e) => {
+                warn!("Reconnect failed: {}", e);
+                // keep the watchdog running, `dish` None would mean replay mode
+                self.dish = Some(Box::new(dish_controller::DisconnectedController {
+                    port_name: port,
+                }));
+            }
         }
     }
+}
+
+impl App {
+    /// the last lines received from the dish, control characters escaped so
+    /// the firmware's cursor movements show up instead of garbling the screen
+    fn render_raw_lines(&self, area: Rect, buf: &mut Buffer) {
+        let height = area.height.saturating_sub(2) as usize;
+        let end = self.raw_lines.len().saturating_sub(self.raw_lines_scroll);
+        let start = end.saturating_sub(height);
+        let lines: Vec<Line> = self
+            .raw_lines
+            .range(start..end)
+            .map(|line| Line::from(line.escape_debug().to_string()))
+            .collect();
+
+        let title = if self.raw_lines_scroll > 0 {
+            format!(" Serial (scrolled up {}) ", self.raw_lines_scroll)
+        } else {
+            " Serial ".to_string()
+        };
+        Paragraph::new(lines)
+            .block(Block::bordered().title(title))
+            .render(area, buf);
+    }
 
-    fn exit(&mut self) {
-        self.should_quit = true;
+    /// sparkline of the recent rf powers, scaled between their min and max
+    /// so small variations on top of the noise floor stay visible
+    fn render_power_history(&self, area: Rect, buf: &mut Buffer) {
+        let min = self
+            .power_history
+            .iter()
+            .copied()
+            .fold(f64::INFINITY, f64::min);
+        let max = self
+            .power_history
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let title = if self.power_history.is_empty() {
+            " Signal ".to_string()
+        } else {
+            format!(" Signal  min {:.1}  max {:.1} ", min, max)
+        };
+
+        // the sparkline only shows the most recent samples that fit
+        let width = area.width.saturating_sub(2) as usize;
+        let data: Vec<u64> = self
+            .power_history
+            .iter()
+            .skip(self.power_history.len().saturating_sub(width))
+            .map(|p| ((p - min) * 10.0) as u64)
+            .collect();
+
+        Sparkline::default()
+            .block(Block::bordered().title(title))
+            .style(Style::default().fg(Color::Cyan))
+            .data(&data)
+            .render(area, buf);
+    }
+}
+
+/// position and signal of a dish other than the first
+fn render_extra_dish(dish: usize, extra: &ExtraDish, area: Rect, buf: &mut Buffer) {
+    let state = &extra.state;
+    let mut state_text = vec![Line::from(format!("Dish {}", dish + 1).bold())];
+    if state.no_data {
+        state_text.push(Line::from("NO DATA".red().bold()));
+    }
+    state_text.extend([
+        Line::from("Port: "),
+        Line::from(extra.controller.port_name().to_string().yellow()),
+        Line::from("Azimuth: "),
+        Line::from(format!("{:.4}°", state.azimuth_angle).yellow()),
+        Line::from("Elevation: "),
+        Line::from(format!("{:.4}°", state.elevation_angle).yellow()),
+        Line::from("Signal (avg): "),
+        Line::from(
+            match state.rf_smoothing.value() {
+                Some(smoothed) => format!("{:.1}", smoothed),
+                None => "-".to_string(),
+            }
+            .yellow(),
+        ),
+    ]);
+    if state.saturated {
+        state_text.push(Line::from("SATURATED".red().bold()));
     }
+    Paragraph::new(state_text)
+        .block(Block::new())
+        .render(area, buf);
 }
 
 impl Widget for &App {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let main_layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Fill(1), Constraint::Length(3)])
+            .constraints(vec![
+                Constraint::Fill(1),
+                Constraint::Length(if self.action_progress.is_some() { 1 } else { 0 }),
+                Constraint::Length(3),
+            ])
             .split(area);
 
+        // a column per dish next to the log
+        let mut columns = vec![Constraint::Fill(1), Constraint::Length(20)];
+        columns.extend(self.extra_dishes.iter().map(|_| Constraint::Length(20)));
         let upper_layout = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints(vec![Constraint::Fill(1), Constraint::Length(20)])
+            .constraints(columns)
             .split(main_layout[0]);
 
-        TuiLoggerSmartWidget::default()
-            .style_error(Style::default().fg(Color::Red))
-            .style_debug(Style::default().fg(Color::Green))
-            .style_warn(Style::default().fg(Color::Yellow))
-            .style_trace(Style::default().fg(Color::Magenta))
-            .style_info(Style::default().fg(Color::Cyan))
-            .output_separator(':')
-            .output_timestamp(Some("%H:%M:%S".to_string()))
-            .output_level(Some(tui_logger::TuiLoggerLevelOutput::Abbreviated))
-            .output_target(true)
-            .output_file(true)
-            .output_line(true)
-            //.state(self.selected_state())
-            .render(upper_layout[0], buf);
+        for (i, extra) in self.extra_dishes.iter().enumerate() {
+            render_extra_dish(i + 1, extra, upper_layout[i + 2], buf);
+        }
+
+        let left_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Fill(1), Constraint::Length(8)])
+            .split(upper_layout[0]);
+
+        if self.show_heatmap {
+            self.heatmap.render(left_layout[0], buf);
+        } else if self.show_polar {
+            let state = self.state.read().unwrap().snapshot();
+            polar::PolarPlot {
+                heatmap: &self.heatmap,
+                position: (state.azimuth_angle, state.elevation_angle),
+            }
+            .render(left_layout[0], buf);
+        } else if self.show_raw_lines {
+            self.render_raw_lines(left_layout[0], buf);
+        } else {
+            TuiLoggerSmartWidget::default()
+                .style_error(Style::default().fg(Color::Red))
+                .style_debug(Style::default().fg(Color::Green))
+                .style_warn(Style::default().fg(Color::Yellow))
+                .style_trace(Style::default().fg(Color::Magenta))
+                .style_info(Style::default().fg(Color::Cyan))
+                .output_separator(':')
+                .output_timestamp(self.log_columns.timestamp.format())
+                .output_level(Some(tui_logger::TuiLoggerLevelOutput::Abbreviated))
+                .output_target(self.log_columns.target)
+                .output_file(self.log_columns.file)
+                .output_line(self.log_columns.line)
+                //.state(self.selected_state())
+                .render(left_layout[0], buf);
+        }
+
+        self.render_power_history(left_layout[1], buf);
 
         {
-            let state = self.state.read().unwrap();
+            let state = self.state.read().unwrap().snapshot();
 
-            let state_text = vec![
+            let mut state_text = vec![];
+            if state.no_data {
+                state_text.push(Line::from("NO DATA FROM THE DISH".red().bold()));
+            }
+            state_text.extend([
                 Line::from("Port: "),
-                Line::from(self.dish.serial_port_name.clone().yellow()),
+                Line::from(
+                    match &self.dish {
+                        Some(dish) => dish.port_name().to_string(),
+                        None => "replay".to_string(),
+                    }
+                    .yellow(),
+                ),
                 Line::from("Azimuth (count): "),
                 Line::from(state.azimuth_count.to_string().yellow()),
                 Line::from("Elevation (count): "),
@@ -332,14 +2533,108 @@ impl Widget for &App {
                 Line::from(format!("{:.4}°", state.azimuth_angle).yellow()),
                 Line::from("Elevation: "),
                 Line::from(format!("{:.4}°", state.elevation_angle).yellow()),
-                Line::from("Signal: "),
-                Line::from(state.signal_strength.to_string().yellow()),
-            ];
+                Line::from("Signal (avg): "),
+                Line::from(
+                    match state.smoothed_signal {
+                        Some(smoothed) => format!("{:.1}", smoothed),
+                        None => "-".to_string(),
+                    }
+                    .yellow(),
+                ),
+                Line::from("Noise floor / SNR: "),
+                Line::from(
+                    match (state.noise_floor, state.snr_db) {
+                        (Some(floor), Some(snr)) => format!("{:.0} / {:+.2} dB", floor, snr),
+                        _ => "-".to_string(),
+                    }
+                    .yellow(),
+                ),
+                Line::from("Peak: "),
+                Line::from(
+                    match &self.best_sample {
+                        Some(best) => format!(
+                            "{:.1} @ {:.1}/{:.1}",
+                            best.power, best.azimuth, best.elevation
+                        ),
+                        None => "-".to_string(),
+                    }
+                    .yellow(),
+                ),
+            ]);
+            if let Some(target) = self.satellite_target {
+                // how far to drive the dish to get onto the satellite
+                let d_az = (target.azimuth - state.azimuth_angle + 180.0).rem_euclid(360.0) - 180.0;
+                let d_el = target.elevation - state.elevation_angle;
+                state_text.extend([
+                    Line::from("Satellite: "),
+                    Line::from(format!("{:.2}/{:.2}", target.azimuth, target.elevation).yellow()),
+                    Line::from("To go (az/el): "),
+                    Line::from(format!("{:+.2}/{:+.2}", d_az, d_el).yellow()),
+                ]);
+            }
+            if state.saturated {
+                state_text.push(Line::from("SATURATED".red().bold()));
+            }
+            if let Some((az, el)) = state.hold {
+                state_text.push(Line::from(vec![
+                    "HOLD".black().on_green().bold(),
+                    format!(" {:.2}/{:.2}", az, el).yellow(),
+                ]));
+            }
+            if state.firmware_scan {
+                state_text.push(Line::from("FIRMWARE SCAN".black().on_yellow().bold()));
+            }
+            if self
+                .limit_flash
+                .is_some_and(|flash| flash.elapsed() < LIMIT_FLASH)
+            {
+                state_text.push(Line::from("AT SOFT LIMIT".black().on_red().bold()));
+            }
             Paragraph::new(state_text)
                 .block(Block::new())
                 .render(upper_layout[1], buf);
         }
 
+        if let Some((done, total)) = self.action_progress {
+            let ratio = if total == 0 {
+                1.0
+            } else {
+                done as f64 / total as f64
+            };
+            Gauge::default()
+                .gauge_style(Style::default().fg(Color::Blue))
+                .ratio(ratio.min(1.0))
+                .label(format!("Scan {}/{}", done, total))
+                .render(main_layout[1], buf);
+        }
+
+        if let Some(form) = self.scan_form.as_ref().or(self.goto_form.as_ref()) {
+            Paragraph::new(form.lines())
+                .block(Block::new())
+                .render(main_layout[2], buf);
+            return;
+        }
+
+        if let Some(input) = &self.command_input {
+            let input_text = vec![
+                Line::from(vec![":".blue().bold(), input.clone().into(), "_".into()]),
+                Line::from(vec![
+                    if self.confirm_command.is_some() {
+                        " Confirm write ".yellow()
+                    } else {
+                        " Send ".into()
+                    },
+                    "<Enter>".blue().bold(),
+                    " Abort ".into(),
+                    "<Esc>".blue().bold(),
+                ]),
+            ];
+            Paragraph::new(input_text)
+                .block(Block::new())
+                .render(main_layout[2], buf);
+            return;
+        }
+
         let bottom_instructions = vec![
             Line::from(vec![
                 " Nudge UP ".into(),
@@ -350,28 +2645,85 @@ impl Widget for &App {
                 "<Left>".blue().bold(),
                 " Nudge CW ".into(),
                 "<Right>".blue().bold(),
+                " Nudge Step ".into(),
+                "<+/->".blue().bold(),
+                format!(" x{}", self.nudge_multiplier).yellow(),
+                " Move ".into(),
+                format!("{}°", self.move_step).yellow(),
+                " <Shift+Arrows>".blue().bold(),
+                " Center on Peak ".into(),
+                "<C>".blue().bold(),
+                " Beamwidth ".into(),
+                "<B>".blue().bold(),
+                " Sky Plot ".into(),
+                "<O>".blue().bold(),
+            ]),
+            Line::from(vec![
+                " Read Signal Level ".into(),
+                "<Space>".blue().bold(),
+                " Cancel Action ".into(),
+                "<Esc>".blue().bold(),
+                " Stop ".into(),
+                "<X>".blue().bold(),
+                " Firmware Scan ".into(),
+                "<F>".blue().bold(),
+                " Hold ".into(),
+                "<L>".blue().bold(),
+                " Toggle Heatmap ".into(),
+                "<H>".blue().bold(),
+                " Serial Lines ".into(),
+                "<W>".blue().bold(),
+                " Log Target/File/Line/Time ".into(),
+                "<1-4>".blue().bold(),
+                " Export PNG ".into(),
+                "<E>".blue().bold(),
+                " Raw Command ".into(),
+                "<:>".blue().bold(),
+                " Reset Peak ".into(),
+                "<R>".blue().bold(),
+                " Stow ".into(),
+                "<P>".blue().bold(),
+                " Scan ".into(),
+                "<S>".blue().bold(),
+                " Scan Form ".into(),
+                "<Shift+S>".blue().bold(),
+                " Go To ".into(),
+                "<G>".blue().bold(),
+                " Mark ".into(),
+                "<M>".blue().bold(),
+                " Back to Mark ".into(),
+                "<Shift+M>".blue().bold(),
+                format!(" {}", self.marks.len()).yellow(),
+                " Track ".into(),
+                "<T>".blue().bold(),
             ]),
-            Line::from(vec![" Read Signal Level ".into(), "<Space>".blue().bold()]),
             Line::from(vec![
                 " Press ".into(),
                 "<Q>".blue().bold(),
-                " to exit the application.".into(),
+                " to exit the application, ".into(),
+                "<Shift+Q>".blue().bold(),
+                " to stow and exit.".into(),
             ]),
         ];
 
         Paragraph::new(bottom_instructions)
             .block(Block::new())
-            .render(main_layout[1], buf);
+            .render(main_layout[2], buf);
     }
 }
 
-fn main() -> io::Result<()> {
-    let (args, actions) = parse_cli_args().unwrap();
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    let (args, actions) = parse_cli_args()?;
+
+    if args.headless {
+        return App::new(args, actions).and_then(|mut app| app.run(None));
+    }
 
-    color_eyre::install().unwrap();
+    // ratatui::init also installs a panic hook that restores the terminal
+    // before the panic message is printed
     let mut terminal = ratatui::init();
-    let mut app = App::new(args, actions).unwrap();
-    let app_result = app.run(&mut terminal);
+    let app_result = App::new(args, actions).and_then(|mut app| app.run(Some(&mut terminal)));
     ratatui::restore();
     app_result
 }
@@ -0,0 +1,100 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+use log::{info, warn};
+
+use crate::dish_controller::DishState;
+
+/// a client that doesn't send its request within this long is dropped, the
+/// connections are served one at a time
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Serves the current `DishState` in the Prometheus text format on any path.
+pub fn start_server(port: u16, state: Arc<RwLock<DishState>>) -> io::Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    info!("Metrics available on http://0.0.0.0:{}/metrics", port);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| serve(stream, &state));
+            if let Err(e) = result {
+                warn!("Metrics request failed: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve(stream: TcpStream, state: &RwLock<DishState>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    // the request itself doesn't matter, just consume its head
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line != "\r\n" {
+        line.clear();
+    }
+
+    let body = render(&state.read().unwrap());
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+fn render(state: &DishState) -> String {
+    let mut body = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+        body.push_str(&format!(
+            "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+        ));
+    };
+
+    metric(
+        "tailgaters_azimuth_degrees",
+        "gauge",
+        "Current azimuth angle",
+        state.azimuth_angle.to_string(),
+    );
+    metric(
+        "tailgaters_elevation_degrees",
+        "gauge",
+        "Current elevation angle",
+        state.elevation_angle.to_string(),
+    );
+    metric(
+        "tailgaters_signal_strength",
+        "gauge",
+        "Last rf power reading",
+        state.signal_strength.to_string(),
+    );
+    metric(
+        "tailgaters_signal_strength_smoothed",
+        "gauge",
+        "Moving average of the rf power readings",
+        state.rf_smoothing.value().unwrap_or_default().to_string(),
+    );
+    metric(
+        "tailgaters_saturated",
+        "gauge",
+        "1 if the last rf reading was over the saturation limit",
+        (state.saturated as u8).to_string(),
+    );
+    metric(
+        "tailgaters_rf_samples_total",
+        "counter",
+        "Number of rf power readings received",
+        state.rf_sample_count.to_string(),
+    );
+
+    body
+}
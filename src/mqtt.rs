@@ -0,0 +1,64 @@
+use std::{thread, time::Duration};
+
+use log::{info, warn};
+use rumqttc::{Client, MqttOptions, QoS};
+
+use crate::{RfPowerRecord, RfPowerSample};
+
+const DEFAULT_MQTT_PORT: u16 = 1883;
+
+/// Publishes rf samples as json to an MQTT broker.
+///
+/// The connection is driven from a background thread that keeps polling through
+/// errors, which makes rumqttc reconnect on its own after a broker outage.
+pub struct MqttPublisher {
+    client: Client,
+    topic: String,
+}
+
+impl MqttPublisher {
+    /// `broker` is `host` or `host:port`
+    pub fn connect(broker: &str, topic: String) -> MqttPublisher {
+        let (host, port) = match broker.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().unwrap_or(DEFAULT_MQTT_PORT)),
+            None => (broker, DEFAULT_MQTT_PORT),
+        };
+
+        let mut options =
+            MqttOptions::new(format!("tailgaters-{}", std::process::id()), host, port);
+        options.set_keep_alive(Duration::from_secs(5));
+
+        let (client, mut connection) = Client::new(options, 64);
+
+        info!("Publishing samples to mqtt://{}:{}/{}", host, port, topic);
+
+        thread::spawn(move || {
+            for notification in connection.iter() {
+                if let Err(e) = notification {
+                    warn!("MQTT connection error, retrying: {}", e);
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        });
+
+        MqttPublisher { client, topic }
+    }
+
+    /// queues the sample without blocking, dropping it if the broker can't keep up
+    pub fn publish(&self, sample: &RfPowerSample) {
+        let payload = match serde_json::to_vec(&RfPowerRecord::from(sample)) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Could not serialize sample for MQTT: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = self
+            .client
+            .try_publish(&self.topic, QoS::AtMostOnce, false, payload)
+        {
+            warn!("Could not publish sample to MQTT: {}", e);
+        }
+    }
+}
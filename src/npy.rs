@@ -0,0 +1,155 @@
+use std::{fs::File, io, io::Write, path::Path};
+
+use crate::output_file::suffixed_path;
+
+/// The power of a Scan2d on its grid, for `--export-npy`. Points without a
+/// sample are NaN.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanGrid {
+    azimuths: Vec<f64>,
+    elevations: Vec<f64>,
+    /// one row per elevation, one column per azimuth
+    power: Vec<f64>,
+}
+
+impl ScanGrid {
+    pub fn new(azimuths: Vec<f64>, elevations: Vec<f64>) -> ScanGrid {
+        ScanGrid {
+            power: vec![f64::NAN; azimuths.len() * elevations.len()],
+            azimuths,
+            elevations,
+        }
+    }
+
+    /// spacing of the grid points, None for a grid of a single point
+    pub fn step(&self) -> Option<f64> {
+        [&self.azimuths, &self.elevations]
+            .into_iter()
+            .find_map(|axis| match axis[..] {
+                [first, second, ..] => Some(second - first),
+                _ => None,
+            })
+    }
+
+    /// Puts a sample in the grid point nearest to where it was taken, unless
+    /// that's more than half a step away. A later sample replaces an earlier
+    /// one.
+    pub fn add(&mut self, azimuth: f64, elevation: f64, power: f64) {
+        let (Some(column), Some(row)) = (
+            nearest(&self.azimuths, azimuth),
+            nearest(&self.elevations, elevation),
+        ) else {
+            return;
+        };
+        self.power[row * self.azimuths.len() + column] = power;
+    }
+
+    /// Writes the power to `path`, and the axis vectors next to it as
+    /// `<name>_az.npy` and `<name>_el.npy`, so that
+    /// `power[i, j]` was taken at `el[i]`, `az[j]`.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_npy(
+            &mut File::create(path)?,
+            &[self.elevations.len(), self.azimuths.len()],
+            &self.power,
+        )?;
+        write_npy(
+            &mut File::create(suffixed_path(path, "az"))?,
+            &[self.azimuths.len()],
+            &self.azimuths,
+        )?;
+        write_npy(
+            &mut File::create(suffixed_path(path, "el"))?,
+            &[self.elevations.len()],
+            &self.elevations,
+        )
+    }
+}
+
+/// index of the axis point closest to `value`, if within half the spacing
+fn nearest(axis: &[f64], value: f64) -> Option<usize> {
+    let step = match axis {
+        [first, second, ..] => (second - first).abs(),
+        _ => f64::INFINITY,
+    };
+    axis.iter()
+        .enumerate()
+        .map(|(i, point)| (i, (point - value).abs()))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .filter(|(_, distance)| *distance <= step / 2.0)
+        .map(|(i, _)| i)
+}
+
+/// Writes `data` as a little endian float64 array of the given shape in
+/// row-major order, in version 1.0 of the .npy format.
+fn write_npy(writer: &mut impl Write, shape: &[usize], data: &[f64]) -> io::Result<()> {
+    let shape = match shape {
+        [length] => format!("({},)", length),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|length| length.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}",
+        shape
+    );
+    // magic, version and header length take 10 bytes, the header is padded
+    // so the data starts aligned to 64
+    let padding = 63 - (10 + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in data {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npy_header() {
+        let mut bytes = vec![];
+        write_npy(&mut bytes, &[2, 3], &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with(" \n"));
+        assert_eq!(bytes.len(), 10 + header_len + 6 * 8);
+        assert_eq!(&bytes[bytes.len() - 8..], &5.0f64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_scan_grid() {
+        let mut grid = ScanGrid::new(vec![180.0, 181.0, 182.0], vec![30.0, 31.0]);
+        // readback a little off the commanded point
+        grid.add(181.05, 30.98, 3400.0);
+        grid.add(179.9, 30.0, 3100.0);
+        // outside the grid
+        grid.add(185.0, 30.0, 9999.0);
+
+        assert_eq!(grid.power[3 + 1], 3400.0);
+        assert_eq!(grid.power[0], 3100.0);
+        assert_eq!(grid.power.iter().filter(|p| p.is_nan()).count(), 4);
+
+        assert_eq!(grid.step(), Some(1.0));
+        assert_eq!(
+            ScanGrid::new(vec![180.0], vec![30.0, 32.5]).step(),
+            Some(2.5)
+        );
+        assert_eq!(ScanGrid::new(vec![180.0], vec![30.0]).step(), None);
+    }
+}
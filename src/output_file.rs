@@ -0,0 +1,133 @@
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+/// When a sample file is closed and the next samples go to a new one
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rotation {
+    pub max_age: Option<Duration>,
+    pub max_bytes: Option<u64>,
+}
+
+/// A file samples are appended to, opened on the first sample so sessions
+/// without readings leave no empty files. With a `Rotation` it rolls over to
+/// `<name>_2.csv`, `<name>_3.csv`... so a crash only costs the current part.
+pub struct OutputFile {
+    path: PathBuf,
+    rotation: Rotation,
+    /// 0 for the file at `path` itself
+    part: usize,
+    file: Option<(File, Instant)>,
+}
+
+impl OutputFile {
+    pub fn new(path: PathBuf, rotation: Rotation) -> OutputFile {
+        OutputFile {
+            path,
+            rotation,
+            part: 0,
+            file: None,
+        }
+    }
+
+    /// path of the part currently written to
+    pub fn path(&self) -> PathBuf {
+        if self.part == 0 {
+            self.path.clone()
+        } else {
+            suffixed_path(&self.path, &(self.part + 1).to_string())
+        }
+    }
+
+    /// The file for the next sample, rolling over first if the current part
+    /// is due. `new_file` is given every part that's still empty once opened,
+    /// to write its header.
+    pub fn get(
+        &mut self,
+        new_file: impl FnOnce(&mut File, &Path) -> io::Result<()>,
+    ) -> io::Result<&mut File> {
+        if let Some((file, opened)) = &self.file {
+            let too_old = self
+                .rotation
+                .max_age
+                .is_some_and(|max_age| opened.elapsed() >= max_age);
+            let too_big = match self.rotation.max_bytes {
+                Some(max_bytes) => file.metadata()?.len() >= max_bytes,
+                None => false,
+            };
+            if too_old || too_big {
+                self.file = None;
+                self.part += 1;
+            }
+        }
+
+        if self.file.is_none() {
+            let path = self.path();
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            if file.metadata()?.len() == 0 {
+                new_file(&mut file, &path)?;
+            }
+            info!("Recording samples to {}", path.display());
+            self.file = Some((file, Instant::now()));
+        }
+
+        Ok(&mut self.file.as_mut().unwrap().0)
+    }
+}
+
+/// `path` with `_<suffix>` added to the file name before the extension, e.g.
+/// rf_power_1741745129_dish2.csv
+pub fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}_{}", stem, suffix);
+    if let Some(extension) = path.extension() {
+        name = format!("{}.{}", name, extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_rotation_by_size() {
+        let dir = std::env::temp_dir().join(format!("tailgaters_rotation_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rf_power.csv");
+
+        let mut output = OutputFile::new(
+            path.clone(),
+            Rotation {
+                max_age: None,
+                max_bytes: Some(20),
+            },
+        );
+        for i in 0..4 {
+            let file = output
+                .get(|file, _| writeln!(file, "elapsed_s,power"))
+                .unwrap();
+            writeln!(file, "{},3100", i).unwrap();
+        }
+
+        // the header alone fills most of a part, so every part gets one sample
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "elapsed_s,power\n0,3100\n"
+        );
+        assert_eq!(output.path(), dir.join("rf_power_4.csv"));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("rf_power_4.csv")).unwrap(),
+            "elapsed_s,power\n3,3100\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
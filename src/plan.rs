@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use serde::Deserialize;
+
+use crate::{
+    dish_actions::{DishAction, DishPosition, Scan2DParams},
+    Cli,
+};
+
+/// A list of targets to scan one after the other, loaded with `--plan` from
+/// a TOML or JSON file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plan {
+    #[serde(rename = "target")]
+    pub targets: Vec<PlanTarget>,
+}
+
+/// One scan of the plan, step, dwell, stop_on_power and samples_per_point
+/// default to the command line values.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanTarget {
+    pub name: String,
+    pub az_start: f64,
+    pub az_end: f64,
+    pub el_start: f64,
+    pub el_end: f64,
+    pub step: Option<f64>,
+    pub dwell_ms: Option<u64>,
+    pub stop_on_power: Option<f64>,
+    pub samples_per_point: Option<u32>,
+}
+
+impl Plan {
+    pub fn load(path: &Path) -> Result<Plan> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("could not read plan {}", path.display()))?;
+
+        let plan = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).map_err(color_eyre::Report::from)
+        } else {
+            toml::from_str(&text).map_err(color_eyre::Report::from)
+        };
+        plan.wrap_err_with(|| format!("could not parse plan {}", path.display()))
+    }
+
+    /// One named `Scan2d` per target, in the order of the file.
+    pub fn into_actions(self, args: &Cli) -> Vec<DishAction> {
+        self.targets
+            .into_iter()
+            .map(|target| {
+                DishAction::Scan2d(Scan2DParams {
+                    bottom_left: DishPosition {
+                        azimuth: target.az_start,
+                        elevation: target.el_start,
+                    },
+                    top_right: DishPosition {
+                        azimuth: target.az_end,
+                        elevation: target.el_end,
+                    },
+                    step: target.step.unwrap_or(args.step),
+                    dwell_ms: target.dwell_ms.unwrap_or(args.dwell_ms),
+                    name: Some(target.name),
+                    stop_on_power: target.stop_on_power.or(args.stop_on_power),
+                    samples_per_point: target.samples_per_point.unwrap_or(args.samples_per_point),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads a `--waypoints` file: one `azimuth, elevation` pair per line, comma
+/// or space separated, `#` starts a comment.
+pub fn load_waypoints(path: &Path) -> Result<Vec<DishPosition>> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("could not read waypoints {}", path.display()))?;
+    parse_waypoints(&text).wrap_err_with(|| format!("could not parse waypoints {}", path.display()))
+}
+
+fn parse_waypoints(text: &str) -> Result<Vec<DishPosition>> {
+    let mut points = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let values: Vec<&str> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .collect();
+        let [azimuth, elevation] = values[..] else {
+            return Err(eyre!(
+                "line {}: expected an azimuth and an elevation, got {:?}",
+                number + 1,
+                line
+            ));
+        };
+        let parse = |value: &str| {
+            value
+                .parse::<f64>()
+                .wrap_err_with(|| format!("line {}: {:?} isn't a number", number + 1, value))
+        };
+        points.push(DishPosition {
+            azimuth: parse(azimuth)?,
+            elevation: parse(elevation)?,
+        });
+    }
+
+    if points.is_empty() {
+        return Err(eyre!("no waypoints"));
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_waypoints() {
+        let points = parse_waypoints(
+            "# astra, hotbird\n\
+             160.2, 30.5\n\
+             \n\
+             171.0 32   # second\n",
+        )
+        .unwrap();
+        assert_eq!(
+            points,
+            vec![
+                DishPosition {
+                    azimuth: 160.2,
+                    elevation: 30.5
+                },
+                DishPosition {
+                    azimuth: 171.0,
+                    elevation: 32.0
+                },
+            ]
+        );
+
+        assert!(parse_waypoints("160.2\n").is_err());
+        assert!(parse_waypoints("160.2, high\n").is_err());
+        assert!(parse_waypoints("# nothing\n").is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Widget};
+
+use crate::heatmap::Heatmap;
+
+/// terminal cells are about twice as tall as they are wide
+const CELL_ASPECT: f64 = 2.0;
+
+/// The heatmap samples on a sky plot: azimuth around the circle with north
+/// up and east to the right, elevation as the radius from the horizon at the
+/// edge to the zenith in the middle. The way antenna patterns are usually
+/// drawn, without the distortion of the rectangular heatmap near the zenith.
+pub struct PolarPlot<'a> {
+    pub heatmap: &'a Heatmap,
+    /// azimuth, elevation of the dish, marked with a `+`
+    pub position: (f64, f64),
+}
+
+/// Azimuth and elevation at an offset from the center of the plot, in units
+/// of the plot radius with y growing downwards. None outside the horizon.
+fn sky_position(x: f64, y: f64) -> Option<(f64, f64)> {
+    let r = x.hypot(y);
+    if r > 1.0 {
+        return None;
+    }
+    let azimuth = x.atan2(-y).to_degrees().rem_euclid(360.0);
+    Some((azimuth, 90.0 * (1.0 - r)))
+}
+
+/// inverse of `sky_position`
+fn plot_offset(azimuth: f64, elevation: f64) -> (f64, f64) {
+    let r = 1.0 - elevation.clamp(0.0, 90.0) / 90.0;
+    let angle = azimuth.to_radians();
+    (r * angle.sin(), -r * angle.cos())
+}
+
+impl Widget for PolarPlot<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut title = match self.heatmap.peak() {
+            Some((az, el, power)) => {
+                format!(" Sky  peak {:.1} @ {:.1}/{:.1} ", power, az, el)
+            }
+            None => " Sky ".to_string(),
+        };
+        if let Some((az, el)) = self.heatmap.reference() {
+            title.push_str(&format!(" ref {:.1}/{:.1} ", az, el));
+        }
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        block.render(area, buf);
+        if inner.width < 3 || inner.height < 3 {
+            return;
+        }
+
+        // radius in rows, as large as fits both ways
+        let radius =
+            ((inner.height as f64 - 1.0) / 2.0).min((inner.width as f64 - 1.0) / 2.0 / CELL_ASPECT);
+        let center_x = inner.x as f64 + inner.width as f64 / 2.0;
+        let center_y = inner.y as f64 + inner.height as f64 / 2.0;
+        let range = self.heatmap.power_range();
+
+        for row in inner.top()..inner.bottom() {
+            for column in inner.left()..inner.right() {
+                let x = (column as f64 + 0.5 - center_x) / CELL_ASPECT / radius;
+                let y = (row as f64 + 0.5 - center_y) / radius;
+                let Some((azimuth, elevation)) = sky_position(x, y) else {
+                    continue;
+                };
+
+                let cell = &mut buf[(column, row)];
+                match (self.heatmap.power_at(azimuth, elevation), range) {
+                    (Some(power), Some((p_min, p_max))) => {
+                        let t = if p_max > p_min {
+                            (power - p_min) / (p_max - p_min)
+                        } else {
+                            1.0
+                        };
+                        cell.set_style(Style::default().bg(self.heatmap.ramp().color(t)));
+                    }
+                    // rings every 30° of elevation, the outer one is the horizon
+                    _ if (elevation % 30.0) < 90.0 / radius / 2.0 => {
+                        cell.set_char('·').set_fg(Color::DarkGray);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let markers = self
+            .heatmap
+            .reference()
+            .map(|reference| (reference, 'R'))
+            .into_iter()
+            .chain([(self.position, '+')]);
+        for ((azimuth, elevation), marker) in markers {
+            let (x, y) = plot_offset(azimuth, elevation);
+            let column = (center_x + x * radius * CELL_ASPECT).floor() as u16;
+            let row = (center_y + y * radius).floor() as u16;
+            if inner.contains((column, row).into()) {
+                buf[(column, row)]
+                    .set_char(marker)
+                    .set_style(Style::default().fg(Color::White).bg(Color::Black));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sky_position() {
+        // any azimuth at the zenith
+        assert_eq!(sky_position(0.0, 0.0).map(|(_, el)| el), Some(90.0));
+        // north is up, east to the right
+        assert_eq!(sky_position(0.0, -1.0), Some((0.0, 0.0)));
+        assert_eq!(sky_position(1.0, 0.0), Some((90.0, 0.0)));
+        assert_eq!(sky_position(0.0, 1.1), None);
+
+        for (azimuth, elevation) in [(160.2, 30.5), (271.0, 5.0), (10.0, 80.0)] {
+            let (x, y) = plot_offset(azimuth, elevation);
+            let (az, el) = sky_position(x, y).unwrap();
+            assert!((az - azimuth).abs() < 1e-9 && (el - elevation).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+};
+
+use log::{info, warn};
+
+use crate::{
+    dish_actions::{DishPosition, MotionOptions},
+    dish_controller::DishState,
+    GlobalBus,
+};
+
+/// hamlib return codes
+const RPRT_OK: i32 = 0;
+const RPRT_EINVAL: i32 = -1;
+const RPRT_ENIMPL: i32 = -4;
+
+/// Minimal Hamlib `rotctld` server, so gpredict and other hamlib clients can
+/// drive the dish as a rotator.
+///
+/// Supports `P <az> <el>` (set position), `p` (get position) and `S` (stop),
+/// plus their `\set_pos`, `\get_pos` and `\stop` long forms. There's no
+/// authentication, anyone who can connect can move the dish.
+pub struct RotctldServer {
+    tx_channel: crossbeam::channel::Sender<GlobalBus>,
+    state: Arc<RwLock<DishState>>,
+    cancel: Arc<AtomicBool>,
+    motion: MotionOptions,
+}
+
+impl RotctldServer {
+    pub fn new(
+        tx_channel: crossbeam::channel::Sender<GlobalBus>,
+        state: Arc<RwLock<DishState>>,
+        cancel: Arc<AtomicBool>,
+        motion: MotionOptions,
+    ) -> RotctldServer {
+        RotctldServer {
+            tx_channel,
+            state,
+            cancel,
+            motion,
+        }
+    }
+
+    /// binds the address and port and serves clients from a background thread
+    pub fn start(self, address: IpAddr, port: u16) -> io::Result<()> {
+        let listener = TcpListener::bind((address, port))?;
+        info!("rotctld server listening on {}:{}", address, port);
+
+        let server = Arc::new(self);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let server = server.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = server.handle_client(stream) {
+                                warn!("rotctld client error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => warn!("rotctld accept failed: {}", e),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn handle_client(&self, stream: TcpStream) -> io::Result<()> {
+        let peer = stream.peer_addr()?;
+        info!("rotctld client connected: {}", peer);
+
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            let reply = self.handle_line(line.trim());
+            match reply {
+                Some(reply) => writer.write_all(reply.as_bytes())?,
+                None => break,
+            }
+        }
+
+        info!("rotctld client disconnected: {}", peer);
+        Ok(())
+    }
+
+    /// Returns the reply for a protocol line, or None if the client asked to quit.
+    fn handle_line(&self, line: &str) -> Option<String> {
+        let mut parts = line.split_whitespace();
+        let reply = match parts.next() {
+            Some("P") | Some("\\set_pos") => {
+                let az = parts.next().and_then(|v| v.parse::<f64>().ok());
+                let el = parts.next().and_then(|v| v.parse::<f64>().ok());
+                match (az, el) {
+                    (Some(azimuth), Some(elevation)) => self.set_position(azimuth, elevation),
+                    _ => report(RPRT_EINVAL),
+                }
+            }
+            Some("p") | Some("\\get_pos") => {
+                let state = self.state.read().unwrap();
+                format!("{:.2}\n{:.2}\n", state.azimuth_angle, state.elevation_angle)
+            }
+            Some("S") | Some("\\stop") => {
+                self.cancel.store(true, Ordering::Relaxed);
+                if self.tx_channel.send(GlobalBus::StopMotion).is_err() {
+                    return Some(report(RPRT_EINVAL));
+                }
+                report(RPRT_OK)
+            }
+            Some("q") | Some("Q") => return None,
+            Some(_) => report(RPRT_ENIMPL),
+            None => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn set_position(&self, azimuth: f64, elevation: f64) -> String {
+        let position = DishPosition { azimuth, elevation };
+        let (validated, commands) = {
+            let state = self.state.read().unwrap();
+            (
+                position.validate(&state),
+                [
+                    self.motion.azimuth_command(&state, azimuth),
+                    self.motion.elevation_command(&state, elevation),
+                ],
+            )
+        };
+        if let Err(e) = validated {
+            warn!("rotctld: rejected position: {}", e);
+            return report(RPRT_EINVAL);
+        }
+
+        info!(
+            "rotctld: moving to azimuth: {:.2}, elevation: {:.2}",
+            azimuth, elevation
+        );
+        for command in commands {
+            if self
+                .tx_channel
+                .send(GlobalBus::DishCommand(command))
+                .is_err()
+            {
+                return report(RPRT_EINVAL);
+            }
+        }
+        report(RPRT_OK)
+    }
+}
+
+fn report(code: i32) -> String {
+    format!("RPRT {}\n", code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dish_driver::{DishCommand, DishResponse};
+
+    #[test]
+    fn test_handle_line() {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let mut state = DishState::default();
+        state.update_from_response(&DishResponse::Azimuth(3622, 180.0));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let server = RotctldServer::new(
+            tx,
+            Arc::new(RwLock::new(state)),
+            cancel.clone(),
+            MotionOptions {
+                elevation_maintain: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(server.handle_line("p").unwrap(), "180.00\n0.00\n");
+
+        assert_eq!(server.handle_line("P 160.5 30").unwrap(), "RPRT 0\n");
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(GlobalBus::DishCommand(DishCommand::SetAzimuthAngle(angle))) if angle == 160.5
+        ));
+        // the motion options apply like to every other move
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(GlobalBus::DishCommand(DishCommand::SetElevationMaintain(_)))
+        ));
+
+        assert_eq!(server.handle_line("P 160.5 95").unwrap(), "RPRT -1\n");
+        assert_eq!(server.handle_line("P 160.5").unwrap(), "RPRT -1\n");
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(server.handle_line("S").unwrap(), "RPRT 0\n");
+        assert!(cancel.load(Ordering::Relaxed));
+        assert!(matches!(rx.try_recv(), Ok(GlobalBus::StopMotion)));
+
+        assert_eq!(server.handle_line("\\dump_caps").unwrap(), "RPRT -4\n");
+        assert_eq!(server.handle_line("q"), None);
+    }
+}
@@ -0,0 +1,131 @@
+use serde::Serialize;
+
+use crate::RfPowerSample;
+
+/// Running statistics of the samples of one scan, updated as they come in so
+/// nothing needs to be kept around until the end.
+#[derive(Debug, Default)]
+pub struct ScanStats {
+    target: Option<String>,
+    count: u64,
+    mean: f64,
+    /// sum of squared differences from the mean (Welford)
+    m2: f64,
+    min: f64,
+    max: f64,
+    peak: Option<(f64, f64)>,
+}
+
+/// What gets logged and written with `--scan-summary` when a scan finishes
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ScanSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub samples: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub peak_azimuth: f64,
+    pub peak_elevation: f64,
+}
+
+impl ScanStats {
+    pub fn new(target: Option<String>) -> Self {
+        ScanStats {
+            target,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, sample: &RfPowerSample) {
+        self.count += 1;
+        let delta = sample.power - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (sample.power - self.mean);
+
+        if self.peak.is_none() || sample.power > self.max {
+            self.max = sample.power;
+            self.peak = Some((sample.azimuth, sample.elevation));
+        }
+        if self.count == 1 || sample.power < self.min {
+            self.min = sample.power;
+        }
+    }
+
+    /// None if the scan didn't record any sample
+    pub fn summary(&self) -> Option<ScanSummary> {
+        let (peak_azimuth, peak_elevation) = self.peak?;
+        Some(ScanSummary {
+            target: self.target.clone(),
+            samples: self.count,
+            min: self.min,
+            max: self.max,
+            mean: self.mean,
+            stddev: (self.m2 / self.count as f64).sqrt(),
+            peak_azimuth,
+            peak_elevation,
+        })
+    }
+}
+
+impl std::fmt::Display for ScanSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} samples, min {:.1}, max {:.1}, mean {:.1}, stddev {:.1}; best: {:.0} @ {:.1}/{:.1}",
+            self.samples,
+            self.min,
+            self.max,
+            self.mean,
+            self.stddev,
+            self.max,
+            self.peak_azimuth,
+            self.peak_elevation
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(power: f64, azimuth: f64, elevation: f64) -> RfPowerSample {
+        RfPowerSample {
+            power,
+            azimuth,
+            elevation,
+            elapsed: std::time::Duration::ZERO,
+            timestamp: std::time::SystemTime::now(),
+            target: None,
+            power_stddev: None,
+        }
+    }
+
+    #[test]
+    fn test_scan_summary() {
+        let mut stats = ScanStats::new(None);
+        assert_eq!(stats.summary(), None);
+
+        for (power, az, el) in [
+            (3000.0, 180.0, 40.0),
+            (4210.0, 182.0, 43.0),
+            (3500.0, 184.0, 40.0),
+        ] {
+            stats.add(&sample(power, az, el));
+        }
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.samples, 3);
+        assert_eq!(summary.min, 3000.0);
+        assert_eq!(summary.max, 4210.0);
+        assert!((summary.mean - 3570.0).abs() < 1e-9);
+        // population stddev of the three powers
+        assert!((summary.stddev - 496.4541).abs() < 1e-3);
+        assert_eq!(
+            (summary.peak_azimuth, summary.peak_elevation),
+            (182.0, 43.0)
+        );
+        assert!(summary.to_string().ends_with("best: 4210 @ 182.0/43.0"));
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use log::{debug, info};
+
+use crate::{
+    dish_actions::DishPosition,
+    dish_controller::{
+        azimuth_distance, AzimuthCalibration, DishController, ElevationCalibration,
+        AZIMUTH_ANGLE_MAX,
+    },
+    dish_driver::{DishCommand, DishResponse, NUDGE_DEG},
+    error::DishError,
+    GlobalBus,
+};
+
+/// how often the simulated position is updated and reported
+const TICK: Duration = Duration::from_millis(100);
+const AZIMUTH_SPEED_DEG_S: f64 = 10.0;
+const ELEVATION_SPEED_DEG_S: f64 = 5.0;
+/// mechanical elevation stops, a little past the default calibration like on
+/// a unit that was never calibrated
+const ELEVATION_STOPS_DEG: (f64, f64) = (-1.5, 72.0);
+
+const NOISE_FLOOR: f64 = 3100.0;
+const PEAK_GAIN: f64 = 1000.0;
+/// standard deviation of the simulated beam, in degrees
+const PEAK_WIDTH_DEG: f64 = 3.0;
+const NOISE_AMPLITUDE: f64 = 10.0;
+
+struct Motion {
+    azimuth: f64,
+    elevation: f64,
+    target_azimuth: f64,
+    target_elevation: f64,
+    /// xorshift state for the rf noise
+    noise_seed: u64,
+}
+
+impl Motion {
+    fn step(&mut self) {
+        let dt = TICK.as_secs_f64();
+        self.azimuth = approach(self.azimuth, self.target_azimuth, AZIMUTH_SPEED_DEG_S * dt);
+        self.elevation = approach(
+            self.elevation,
+            self.target_elevation,
+            ELEVATION_SPEED_DEG_S * dt,
+        )
+        .clamp(ELEVATION_STOPS_DEG.0, ELEVATION_STOPS_DEG.1);
+    }
+
+    fn noise(&mut self) -> f64 {
+        self.noise_seed ^= self.noise_seed << 13;
+        self.noise_seed ^= self.noise_seed >> 7;
+        self.noise_seed ^= self.noise_seed << 17;
+        (self.noise_seed as f64 / u64::MAX as f64 - 0.5) * 2.0 * NOISE_AMPLITUDE
+    }
+}
+
+fn approach(current: f64, target: f64, max_step: f64) -> f64 {
+    current + (target - current).clamp(-max_step, max_step)
+}
+
+/// A fake dish for running the TUI and actions without hardware.
+///
+/// The motors drift towards the commanded angles at a fixed speed, the position
+/// is reported like the real polling would, and rf readings follow a 2D gaussian
+/// peak on top of a noisy floor.
+pub struct DishSimulator {
+    motion: Arc<Mutex<Motion>>,
+    peak: DishPosition,
+    /// the calibration the app reads the counts with, so both agree on the
+    /// angles
+    elevation_calibration: ElevationCalibration,
+    nvram: BTreeMap<u16, u16>,
+    mainchan_sender: crossbeam::channel::Sender<GlobalBus>,
+}
+
+impl DishSimulator {
+    pub fn new(
+        peak: DishPosition,
+        elevation_calibration: ElevationCalibration,
+        channel: crossbeam::channel::Sender<GlobalBus>,
+    ) -> Self {
+        info!(
+            "Simulating a dish with a signal at azimuth: {}, elevation: {}",
+            peak.azimuth, peak.elevation
+        );
+
+        let motion = Arc::new(Mutex::new(Motion {
+            azimuth: 180.0,
+            elevation: 0.0,
+            target_azimuth: 180.0,
+            target_elevation: 0.0,
+            noise_seed: 0x2545_f491_4f6c_dd1d,
+        }));
+
+        let sim = DishSimulator {
+            motion,
+            peak,
+            elevation_calibration,
+            nvram: BTreeMap::new(),
+            mainchan_sender: channel,
+        };
+        sim.motion_thread();
+        sim
+    }
+
+    fn motion_thread(&self) {
+        let motion = self.motion.clone();
+        let sender = self.mainchan_sender.clone();
+        let elevation_calibration = self.elevation_calibration;
+
+        thread::spawn(move || loop {
+            let (azimuth, elevation) = {
+                let mut motion = motion.lock().unwrap();
+                motion.step();
+                // the firmware reports the heading modulo 360, also on a
+                // mount that turns further
+                (
+                    motion.azimuth.rem_euclid(AZIMUTH_ANGLE_MAX),
+                    motion.elevation,
+                )
+            };
+
+            let responses = [
+                DishResponse::Azimuth(
+                    AzimuthCalibration::default().angle_to_count(azimuth),
+                    azimuth,
+                ),
+                DishResponse::Elevation(elevation_calibration.angle_to_count(elevation)),
+            ];
+            for response in responses {
+                if sender.send(GlobalBus::DishResponse(0, response)).is_err() {
+                    return;
+                }
+            }
+
+            thread::sleep(TICK);
+        });
+    }
+
+    fn rf_power(&self, motion: &mut Motion) -> f64 {
+        let d_az = azimuth_distance(motion.azimuth, self.peak.azimuth);
+        let d_el = motion.elevation - self.peak.elevation;
+        let distance_sq = d_az * d_az + d_el * d_el;
+        NOISE_FLOOR
+            + PEAK_GAIN * (-distance_sq / (2.0 * PEAK_WIDTH_DEG * PEAK_WIDTH_DEG)).exp()
+            + motion.noise()
+    }
+}
+
+impl DishController for DishSimulator {
+    fn port_name(&self) -> &str {
+        "simulator"
+    }
+
+    fn send_command(&mut self, command: DishCommand) -> Result<(), DishError> {
+        let mut motion = self.motion.lock().unwrap();
+        let response = match command {
+            DishCommand::SetAzimuthAngle(angle) | DishCommand::SetAzimuthHeading(angle) => {
+                motion.target_azimuth = angle;
+                None
+            }
+            DishCommand::SetElevationAngle(angle) => {
+                motion.target_elevation = angle;
+                None
+            }
+            DishCommand::SetElevationCount(count) | DishCommand::SetElevationMaintain(count) => {
+                motion.target_elevation = self.elevation_calibration.count_to_angle(count);
+                None
+            }
+            DishCommand::NudgeAzimuthCw => {
+                motion.target_azimuth += NUDGE_DEG;
+                None
+            }
+            DishCommand::NudgeAzimuthCcw => {
+                motion.target_azimuth -= NUDGE_DEG;
+                None
+            }
+            DishCommand::NudgeElevationUp => {
+                motion.target_elevation += NUDGE_DEG;
+                None
+            }
+            DishCommand::NudgeElevationDown => {
+                motion.target_elevation -= NUDGE_DEG;
+                None
+            }
+            DishCommand::RfWatch(seconds) => {
+                let seconds = seconds.max(1);
+                let total: f64 = (0..seconds).map(|_| self.rf_power(&mut motion)).sum();
+                Some(DishResponse::RfPower(total / seconds as f64))
+            }
+            DishCommand::Version => Some(DishResponse::Ver("simulator".to_string())),
+            // no sweep, but the state is reported like the firmware does
+            DishCommand::ScanStart => Some(DishResponse::ScanState(true)),
+            DishCommand::ScanStop => Some(DishResponse::ScanState(false)),
+            DishCommand::NvRead(addr) => Some(DishResponse::NvValue(
+                addr,
+                self.nvram.get(&addr).copied().unwrap_or_default(),
+            )),
+            DishCommand::NvWrite { addr, value } => {
+                self.nvram.insert(addr, value);
+                None
+            }
+            // the position is reported on every tick anyway
+            DishCommand::GetAzimuth | DishCommand::GetElevation => None,
+            DishCommand::Reset => {
+                info!("simulator reset");
+                None
+            }
+            DishCommand::Raw(line) => {
+                info!("simulator ignoring raw command: {}", line);
+                None
+            }
+        };
+
+        if let Some(response) = response {
+            // the app is shutting down if nobody listens anymore
+            if let Err(e) = self
+                .mainchan_sender
+                .send(GlobalBus::DishResponse(0, response))
+            {
+                debug!("Bus closed, dropping {:?}", e.into_inner());
+            }
+        }
+
+        Ok(())
+    }
+}
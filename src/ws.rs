@@ -0,0 +1,148 @@
+use std::{
+    io,
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender};
+use log::{info, warn};
+use serde::Serialize;
+use tungstenite::WebSocket;
+
+use crate::{dish_controller::DishState, RfPowerRecord, RfPowerSample};
+
+/// how often connected clients get a snapshot of the state
+const STATE_INTERVAL: Duration = Duration::from_millis(500);
+/// a client that can't take a message within this long is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+    State(StateSnapshot),
+    Sample(RfPowerRecord),
+}
+
+#[derive(Serialize)]
+struct StateSnapshot {
+    azimuth: f64,
+    elevation: f64,
+    azimuth_count: i32,
+    elevation_count: i32,
+    signal_strength: f64,
+    signal_smoothed: Option<f64>,
+    saturated: bool,
+    rf_sample_count: u64,
+    target: Option<String>,
+}
+
+impl From<&DishState> for StateSnapshot {
+    fn from(state: &DishState) -> Self {
+        StateSnapshot {
+            azimuth: state.azimuth_angle,
+            elevation: state.elevation_angle,
+            azimuth_count: state.azimuth_count,
+            elevation_count: state.elevation_count,
+            signal_strength: state.signal_strength,
+            signal_smoothed: state.rf_smoothing.value(),
+            saturated: state.saturated,
+            rf_sample_count: state.rf_sample_count,
+            target: state.target.clone(),
+        }
+    }
+}
+
+type Clients = Arc<Mutex<Vec<WebSocket<TcpStream>>>>;
+
+/// Streams json state snapshots and rf samples to any number of WebSocket
+/// clients, for browser dashboards.
+///
+/// Samples are handed to a broadcast thread, so a slow client never holds up
+/// the main loop.
+pub struct WsServer {
+    samples: Sender<RfPowerRecord>,
+}
+
+impl WsServer {
+    pub fn start(port: u16, state: Arc<RwLock<DishState>>) -> io::Result<WsServer> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        info!("Live feed available on ws://0.0.0.0:{}", port);
+
+        let clients: Clients = Arc::new(Mutex::new(vec![]));
+
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let client = stream
+                    .and_then(|stream| {
+                        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                        Ok(stream)
+                    })
+                    .map_err(|e| e.to_string())
+                    .and_then(|stream| tungstenite::accept(stream).map_err(|e| e.to_string()));
+
+                match client {
+                    Ok(client) => {
+                        info!("WebSocket client connected");
+                        accepted.lock().unwrap().push(client);
+                    }
+                    Err(e) => warn!("WebSocket handshake failed: {}", e),
+                }
+            }
+        });
+
+        let (samples, receiver) = crossbeam::channel::unbounded();
+        thread::spawn(move || broadcast_loop(receiver, state, clients));
+
+        Ok(WsServer { samples })
+    }
+
+    pub fn publish(&self, sample: &RfPowerSample) {
+        // the broadcast thread only stops along with the app
+        let _ = self.samples.send(RfPowerRecord::from(sample));
+    }
+}
+
+fn broadcast_loop(
+    receiver: Receiver<RfPowerRecord>,
+    state: Arc<RwLock<DishState>>,
+    clients: Clients,
+) {
+    let mut last_state = Instant::now();
+    loop {
+        match receiver.recv_timeout(STATE_INTERVAL) {
+            Ok(record) => broadcast(&clients, &Message::Sample(record)),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        if last_state.elapsed() >= STATE_INTERVAL {
+            let snapshot = StateSnapshot::from(&*state.read().unwrap());
+            broadcast(&clients, &Message::State(snapshot));
+            last_state = Instant::now();
+        }
+    }
+}
+
+/// sends the message to every client, dropping the ones that went away
+fn broadcast(clients: &Clients, message: &Message) {
+    let text = match serde_json::to_string(message) {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("Could not serialize WebSocket message: {}", e);
+            return;
+        }
+    };
+
+    clients.lock().unwrap().retain_mut(|client| {
+        match client.send(tungstenite::Message::text(text.clone())) {
+            Ok(()) => true,
+            Err(e) => {
+                info!("WebSocket client dropped: {}", e);
+                false
+            }
+        }
+    });
+}